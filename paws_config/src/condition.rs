@@ -0,0 +1,242 @@
+use std::{fmt, str::FromStr};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    // Longer operators go first so `<=` isn't read as `<`
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    pub fn holds<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+        }
+    }
+
+    /// Only equality makes sense for booleans and text
+    pub fn is_ordering(&self) -> bool {
+        !matches!(self, Comparison::Equal | Comparison::NotEqual)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (operator, _) = Comparison::OPERATORS
+            .iter()
+            .find(|(_, comparison)| comparison == self)
+            .unwrap();
+        f.write_str(operator)
+    }
+}
+
+/// Right-hand side of a condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum Threshold {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::Bool(value) => write!(f, "{}", value),
+            Threshold::Number(value) => write!(f, "{}", value),
+            Threshold::Text(value) => write!(f, "\"{}\"", value),
+        }
+    }
+}
+
+fn parse_threshold(raw: &str) -> Threshold {
+    if let Ok(value) = raw.parse::<bool>() {
+        return Threshold::Bool(value);
+    }
+    if let Ok(value) = raw.parse::<f64>() {
+        return Threshold::Number(value);
+    }
+    let unquoted = raw
+        .strip_prefix('"')
+        .and_then(|raw| raw.strip_suffix('"'))
+        .or_else(|| {
+            raw.strip_prefix('\'')
+                .and_then(|raw| raw.strip_suffix('\''))
+        })
+        .unwrap_or(raw);
+    Threshold::Text(unquoted.to_string())
+}
+
+/// Comparison of a status field against a constant, e.g. `error_rate < 0.05` or `healthy == true`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    pub comparison: Comparison,
+    pub threshold: Threshold,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(pos) = s.find(['<', '>', '=', '!']) else {
+            return Err(format!("Expected `field <op> value`, got `{}`", s));
+        };
+        let (field, rest) = s.split_at(pos);
+        let Some((operator, comparison)) = Comparison::OPERATORS
+            .iter()
+            .find(|(operator, _)| rest.starts_with(operator))
+        else {
+            return Err(format!("Unknown operator in `{}`", s));
+        };
+
+        let field = field.trim();
+        let threshold = rest[operator.len()..].trim();
+        if field.is_empty() || threshold.is_empty() {
+            return Err(format!("Expected `field <op> value`, got `{}`", s));
+        }
+
+        let threshold = parse_threshold(threshold);
+        if comparison.is_ordering() && !matches!(threshold, Threshold::Number(_)) {
+            return Err(format!("`{}` only compares numbers, got `{}`", operator, s));
+        }
+
+        Ok(Condition {
+            field: field.to_string(),
+            comparison: *comparison,
+            threshold,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.field, self.comparison, self.threshold)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Condition which has to hold for some time in a row, e.g. `queue_depth > 1000 for PT2M`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SustainedCondition {
+    pub condition: Condition,
+    /// Right away when not set
+    pub sustained: Option<std::time::Duration>,
+}
+
+impl FromStr for SustainedCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((condition, sustained)) = s.rsplit_once(" for ") else {
+            return Ok(SustainedCondition {
+                condition: s.parse()?,
+                sustained: None,
+            });
+        };
+        let sustained = iso8601::duration(sustained.trim())
+            .map_err(|err| format!("Bad duration in `{}`: {}", s, err))?;
+
+        Ok(SustainedCondition {
+            condition: condition.parse()?,
+            sustained: Some(sustained.into()),
+        })
+    }
+}
+
+impl fmt::Display for SustainedCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sustained {
+            Some(sustained) => write!(f, "{} for {}s", self.condition, sustained.as_secs()),
+            None => write!(f, "{}", self.condition),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SustainedCondition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Comparison, Condition, SustainedCondition, Threshold};
+
+    #[test]
+    fn test_parse_conditions() {
+        let condition: Condition = "error_rate < 0.05".parse().unwrap();
+        assert_eq!(condition.field, "error_rate");
+        assert_eq!(condition.comparison, Comparison::Less);
+        assert_eq!(condition.threshold, Threshold::Number(0.05));
+
+        let condition: Condition = "latency>=200".parse().unwrap();
+        assert_eq!(condition.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(condition.threshold, Threshold::Number(200.0));
+
+        let condition: Condition = "healthy == true".parse().unwrap();
+        assert_eq!(condition.threshold, Threshold::Bool(true));
+
+        let condition: Condition = "state != 'degraded'".parse().unwrap();
+        assert_eq!(condition.comparison, Comparison::NotEqual);
+        assert_eq!(condition.threshold, Threshold::Text("degraded".to_string()));
+        assert_eq!(condition.to_string(), "state != \"degraded\"");
+    }
+
+    #[test]
+    fn test_malformed_conditions() {
+        assert!("error_rate".parse::<Condition>().is_err());
+        assert!("< 5".parse::<Condition>().is_err());
+        assert!("errors =< 5".parse::<Condition>().is_err());
+        assert!("state < degraded".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_sustained_conditions() {
+        let sustained: SustainedCondition = "queue_depth > 1000 for PT2M".parse().unwrap();
+        assert_eq!(sustained.condition.field, "queue_depth");
+        assert_eq!(sustained.condition.threshold, Threshold::Number(1000.0));
+        assert_eq!(sustained.sustained, Some(Duration::from_secs(120)));
+
+        let sustained: SustainedCondition = "errors > 10".parse().unwrap();
+        assert_eq!(sustained.sustained, None);
+
+        assert!("errors > 10 for ever"
+            .parse::<SustainedCondition>()
+            .is_err());
+    }
+}
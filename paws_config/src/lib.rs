@@ -1,6 +1,18 @@
+mod condition;
+mod manifest;
+mod overrides;
+
 use std::{collections::HashMap, path::PathBuf};
 use serde::Deserialize;
-#[derive(Debug, Clone)]
+
+pub use condition::{Comparison, Condition, SustainedCondition, Threshold};
+pub use manifest::{
+    load_manifest, EntrypointOptions, PluginKind, PluginManifest, PluginOptionSpec,
+    StatusFormat, MANIFEST_FILE_NAME,
+};
+pub use overrides::ConfigOverride;
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Duration(std::time::Duration);
 
 impl Duration {
@@ -25,24 +37,48 @@ impl<'de> Deserialize<'de> for Duration {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum StartupOptions {
     Hot,
+    #[default]
     Cold,
     #[serde(untagged)]
     Delayed(Duration),
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Clone)]
 pub struct RandomRange<T> {
     pub min: T,
     pub max: T,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl<'de, T> Deserialize<'de> for RandomRange<T>
+where
+    T: Deserialize<'de> + PartialOrd,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Bounds<T> {
+            min: T,
+            max: T,
+        }
+
+        let Bounds { min, max } = Bounds::deserialize(deserializer)?;
+        if min > max {
+            return Err(serde::de::Error::custom("`min` is greater than `max`"));
+        }
+        Ok(RandomRange { min, max })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum FrequencyOptions {
+    #[default]
     Once,
     #[serde(untagged)]
     Fixed(Duration),
@@ -50,6 +86,25 @@ pub enum FrequencyOptions {
     Random(RandomRange<Duration>),
 }
 
+/// How long an injected fault stays active before it's reverted
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum HoldOptions {
+    Fixed(Duration),
+    Random(RandomRange<Duration>),
+}
+
+/// Measures how long it takes to notice a fault and recover from it
+#[derive(Debug, Deserialize, Clone)]
+pub struct RecoveryOptions {
+    /// Steady-state probe telling whether the system is healthy
+    pub probe: String,
+    /// How often the probe is polled after the fault, every second by default
+    pub interval: Option<Duration>,
+    /// Incidents still not recovered by then are reported as such, 1 minute by default
+    pub timeout: Option<Duration>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct GlobalMonitoringOptions {
     pub dsn: String,
@@ -59,25 +114,185 @@ pub struct GlobalMonitoringOptions {
 #[derive(Debug, Deserialize, Clone)]
 pub struct PluginMonitoringOptions {
     pub frequency: FrequencyOptions,
-    pub extra_tags: Option<HashMap<String, String>>
+    pub extra_tags: Option<HashMap<String, String>>,
+    /// Stop running the plugin for the rest of the run once its status meets the condition
+    pub stop_if: Option<SustainedCondition>,
+    /// Hold off running the plugin while its status meets the condition
+    pub pause_if: Option<SustainedCondition>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+pub struct SandboxDir {
+    pub host: PathBuf,
+    pub guest: String,
+    /// Mounted read-only unless set to `false`
+    pub read_only: Option<bool>,
+}
+
+/// Host capabilities granted to sandboxed (WebAssembly) plugins.
+/// Nothing but the plugin options is available to them by default.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SandboxOptions {
+    /// Host env vars passed through to the plugin
+    pub env: Option<Vec<String>>,
+    /// Host directories mounted into the plugin
+    pub dirs: Option<Vec<SandboxDir>>,
+    /// Command lines the plugin is allowed to spawn, the program by absolute path and `*` for any argument
+    pub commands: Option<Vec<String>>,
+}
+
+/// Checks that a plugin name or id is a single path component, so it can't point anywhere else
+pub fn check_plain_name(name: &str) -> Result<(), String> {
+    let mut components = std::path::Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(component)), None) if component == name => Ok(()),
+        _ => Err(format!("`{}` has to be a plain name, not a path", name)),
+    }
+}
+
+/// The id names the state dir and metrics files of the plugin, so it can't point anywhere else
+fn plugin_id<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let id = String::deserialize(deserializer)?;
+    check_plain_name(&id)
+        .map_err(|err| serde::de::Error::custom(format!("Plugin id {}", err)))?;
+    Ok(Some(id))
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct PluginConfig {
     pub name: String,
+    /// Tells apart several configured instances of the same plugin, defaults to the name
+    #[serde(default, deserialize_with = "plugin_id")]
+    pub id: Option<String>,
     pub startup: StartupOptions,
     pub frequency: FrequencyOptions,
+    /// Keep the fault injected by `run` for this long, then revert it
+    pub hold: Option<HoldOptions>,
+    /// Health probe timing detection and recovery of every injected fault
+    pub recovery: Option<RecoveryOptions>,
     pub monitoring: Option<PluginMonitoringOptions>,
-    pub options: Option<HashMap<String, String>>
+    pub options: Option<HashMap<String, String>>,
+    pub tags: Option<Vec<String>>,
+    pub sandbox: Option<SandboxOptions>,
+    /// Interpreter to run a Python plugin in its own process instead of the embedded one
+    pub python: Option<PathBuf>,
+    /// Longest a call of a Python plugin running in its own process may take, 5 minutes by default
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpProbeOptions {
+    pub url: String,
+    /// Expected response code, any 2xx or 3xx by default
+    pub status: Option<i32>,
+    /// Text the response body has to contain
+    pub body: Option<String>,
+    /// Slowest acceptable response
+    pub latency: Option<Duration>,
+    /// Whole seconds only
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TcpProbeOptions {
+    /// `host:port` to connect to
+    pub address: String,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CommandProbeOptions {
+    /// Shell command line
+    pub run: String,
+    /// Expected exit code, 0 by default
+    pub exit_code: Option<i32>,
+}
+
+/// Where a probe reads its status from
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ProbeSource {
+    /// Status of any plugin, called with the given options
+    Plugin {
+        plugin: String,
+        options: Option<HashMap<String, String>>,
+    },
+    /// HTTP(S) GET
+    Http { http: HttpProbeOptions },
+    Tcp { tcp: TcpProbeOptions },
+    /// Host name that has to resolve
+    Dns { dns: String },
+    /// Name of a process that has to be running
+    Process { process: String },
+    Command { command: CommandProbeOptions },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProbeConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub source: ProbeSource,
+    /// Conditions the status has to meet for the system to count as healthy,
+    /// built-in probes just have to succeed by default
+    #[serde(default)]
+    pub tolerance: Vec<Condition>,
+}
+
+/// What a healthy system looks like, checked before, during and after the run
+#[derive(Debug, Deserialize, Clone)]
+pub struct SteadyStateOptions {
+    /// How often the probes are checked during the run, every 10 seconds by default
+    pub frequency: Option<Duration>,
+    pub probes: Vec<ProbeConfig>,
+}
+
+/// Subset of configured plugins selected for a run.
+/// Empty lists don't filter anything out.
+#[derive(Debug, Default, Clone)]
+pub struct PluginFilter {
+    pub only: Vec<String>,
+    pub exclude: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl PluginFilter {
+    pub fn matches(&self, plugin: &PluginConfig) -> bool {
+        if !self.only.is_empty() && !self.only.contains(&plugin.name) {
+            return false;
+        }
+        if self.exclude.contains(&plugin.name) {
+            return false;
+        }
+        if self.tags.is_empty() {
+            return true;
+        }
+
+        plugin
+            .tags
+            .as_ref()
+            .is_some_and(|tags| tags.iter().any(|tag| self.tags.contains(tag)))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct KittypawsConfig {
+    /// Extra directories to look for plugins in, relative to the config file
+    pub plugin_dirs: Option<Vec<PathBuf>>,
     pub monitoring: Option<GlobalMonitoringOptions>,
     pub duration: Option<Duration>,
+    pub steady_state: Option<SteadyStateOptions>,
     pub plugins: Vec<PluginConfig>,
 }
 
+impl KittypawsConfig {
+    pub fn filter_plugins(&mut self, filter: &PluginFilter) {
+        self.plugins.retain(|plugin| filter.matches(plugin));
+    }
+}
+
 impl From<String> for KittypawsConfig {
     fn from(value: String) -> Self {
         serde_yaml::from_str(value.as_str()).unwrap()
@@ -85,19 +300,329 @@ impl From<String> for KittypawsConfig {
 }
 
 pub fn load_config(path: PathBuf) -> KittypawsConfig {
-    let contents = std::fs::read_to_string(path).expect("Should have been able to read the file");
+    load_config_with_overrides(path, &[])
+}
+
+fn parse_config(contents: String, overrides: &[ConfigOverride]) -> KittypawsConfig {
+    if overrides.is_empty() {
+        return KittypawsConfig::from(contents);
+    }
 
-    KittypawsConfig::from(contents)
+    let mut value: serde_yaml::Value = serde_yaml::from_str(&contents).unwrap();
+    for config_override in overrides {
+        if let Err(err) = config_override.apply(&mut value) {
+            panic!("Can't apply config override: {}", err);
+        }
+    }
+
+    // Going back to text keeps scalars typed the same way as in a config file,
+    // e.g. numbers in plugin options are still read as strings
+    KittypawsConfig::from(serde_yaml::to_string(&value).unwrap())
+}
+
+pub fn load_config_with_overrides(path: PathBuf, overrides: &[ConfigOverride]) -> KittypawsConfig {
+    let contents = std::fs::read_to_string(&path).expect("Should have been able to read the file");
+    let mut config = parse_config(contents, overrides);
+
+    if let (Some(plugin_dirs), Some(config_dir)) = (config.plugin_dirs.as_mut(), path.parent()) {
+        for dir in plugin_dirs.iter_mut() {
+            if dir.is_relative() && !dir.starts_with("~") {
+                *dir = config_dir.join(&dir);
+            }
+        }
+    }
+
+    config
 }
 
 #[cfg(test)]
 mod tests {
     use std::{path::PathBuf, str::FromStr};
 
-    use super::load_config;
+    use super::{
+        load_config, load_config_with_overrides, Comparison, ConfigOverride, HoldOptions,
+        KittypawsConfig, PluginFilter, ProbeSource,
+    };
 
     #[test]
     fn test_correct_configs_loading() {
         load_config(PathBuf::from_str("../configs/dumb_test.yml").unwrap());
     }
+
+    #[test]
+    fn test_config_loading_with_overrides() {
+        let overrides: Vec<ConfigOverride> = vec![
+            "duration=PT10M".parse().unwrap(),
+            "plugins[dumbman].options.retries=3".parse().unwrap(),
+        ];
+        let config = load_config_with_overrides(
+            PathBuf::from_str("../configs/dumb_test.yml").unwrap(),
+            &overrides,
+        );
+
+        assert_eq!(config.duration.unwrap().as_std().as_secs(), 600);
+        assert_eq!(
+            config.plugins[0].options.as_ref().unwrap().get("retries"),
+            Some(&"3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hold_durations() {
+        let config: KittypawsConfig = serde_yaml::from_str(
+            "
+plugins:
+- name: dropper
+  startup: hot
+  frequency: once
+  hold: PT5M
+- name: deathloop
+  startup: hot
+  frequency: once
+  hold:
+    min: PT1M
+    max: PT2M
+- name: timeburglar
+  startup: hot
+  frequency: once
+",
+        )
+        .unwrap();
+
+        assert!(
+            matches!(&config.plugins[0].hold, Some(HoldOptions::Fixed(hold)) if hold.as_std().as_secs() == 300)
+        );
+        assert!(
+            matches!(&config.plugins[1].hold, Some(HoldOptions::Random(range)) if range.max.as_std().as_secs() == 120)
+        );
+        assert!(config.plugins[2].hold.is_none());
+
+        let reversed = serde_yaml::from_str::<KittypawsConfig>(
+            "
+plugins:
+- name: dropper
+  startup: hot
+  frequency: once
+  hold:
+    min: PT2M
+    max: PT1M
+",
+        );
+        assert!(reversed.is_err());
+    }
+
+    #[test]
+    fn test_recovery_probe() {
+        let config: KittypawsConfig = serde_yaml::from_str(
+            "
+plugins:
+- name: dropper
+  startup: hot
+  frequency: once
+  recovery:
+    probe: api
+- name: timeburglar
+  startup: hot
+  frequency: once
+",
+        )
+        .unwrap();
+
+        assert_eq!(config.plugins[0].recovery.as_ref().unwrap().probe, "api");
+        assert!(config.plugins[1].recovery.is_none());
+    }
+
+    #[test]
+    fn test_circuit_breaker_thresholds() {
+        let config: KittypawsConfig = serde_yaml::from_str(
+            "
+plugins:
+- name: dropper
+  startup: hot
+  frequency: once
+  monitoring:
+    frequency: PT10S
+    stop_if: errors > 10
+    pause_if: queue_depth > 1000 for PT2M
+",
+        )
+        .unwrap();
+
+        let monitoring = config.plugins[0].monitoring.as_ref().unwrap();
+        assert!(monitoring.stop_if.as_ref().unwrap().sustained.is_none());
+        assert_eq!(
+            monitoring.pause_if.as_ref().unwrap().sustained.unwrap().as_secs(),
+            120
+        );
+    }
+
+    #[test]
+    fn test_plugin_id_is_a_plain_name() {
+        let parse = |id: &str| {
+            serde_yaml::from_str::<KittypawsConfig>(&format!(
+                "
+plugins:
+- name: dropper
+  id: '{}'
+  startup: hot
+  frequency: once
+",
+                id
+            ))
+        };
+
+        assert_eq!(parse("dropper-eu").unwrap().plugins[0].id.as_deref(), Some("dropper-eu"));
+        for id in ["../../etc", "a/b", "/etc", "..", ".", "", "dropper/"] {
+            assert!(parse(id).is_err(), "{} is accepted", id);
+        }
+    }
+
+    #[test]
+    fn test_steady_state() {
+        let config: KittypawsConfig = serde_yaml::from_str(
+            "
+steady_state:
+  frequency: PT5S
+  probes:
+  - name: api
+    plugin: http-check
+    options:
+      url: http://localhost:8080
+    tolerance:
+    - error_rate < 0.05
+    - healthy == true
+plugins: []
+",
+        )
+        .unwrap();
+
+        let steady_state = config.steady_state.unwrap();
+        assert_eq!(steady_state.frequency.unwrap().as_std().as_secs(), 5);
+        let probe = &steady_state.probes[0];
+        assert!(
+            matches!(&probe.source, ProbeSource::Plugin { plugin, .. } if plugin == "http-check")
+        );
+        assert_eq!(probe.tolerance.len(), 2);
+        assert_eq!(probe.tolerance[0].comparison, Comparison::Less);
+    }
+
+    #[test]
+    fn test_builtin_probes() {
+        let config: KittypawsConfig = serde_yaml::from_str(
+            "
+steady_state:
+  probes:
+  - name: api
+    http:
+      url: https://localhost:8443/health
+      status: 200
+      latency: PT0.5S
+  - name: db
+    tcp:
+      address: localhost:5432
+  - name: resolver
+    dns: example.com
+  - name: nginx
+    process: nginx
+  - name: queue
+    command:
+      run: test -e /tmp/queue.sock
+    tolerance:
+    - duration < 1
+plugins: []
+",
+        )
+        .unwrap();
+
+        let probes = config.steady_state.unwrap().probes;
+        assert!(
+            matches!(&probes[0].source, ProbeSource::Http { http } if http.status == Some(200))
+        );
+        assert!(matches!(&probes[1].source, ProbeSource::Tcp { tcp } if tcp.timeout.is_none()));
+        assert!(matches!(&probes[2].source, ProbeSource::Dns { dns } if dns == "example.com"));
+        assert!(matches!(&probes[3].source, ProbeSource::Process { .. }));
+        assert!(
+            matches!(&probes[4].source, ProbeSource::Command { command } if command.exit_code.is_none())
+        );
+        assert!(probes[0].tolerance.is_empty());
+        assert_eq!(probes[4].tolerance.len(), 1);
+    }
+
+    #[test]
+    fn test_plugin_dirs_relative_to_config() {
+        let overrides: Vec<ConfigOverride> = vec!["plugin_dirs=[plugins, /opt/paws]"
+            .parse()
+            .unwrap()];
+        let config = load_config_with_overrides(
+            PathBuf::from_str("../configs/dumb_test.yml").unwrap(),
+            &overrides,
+        );
+
+        assert_eq!(
+            config.plugin_dirs.unwrap(),
+            vec![
+                PathBuf::from("../configs/plugins"),
+                PathBuf::from("/opt/paws")
+            ]
+        );
+    }
+
+    fn tagged_config() -> KittypawsConfig {
+        KittypawsConfig::from(
+            "
+plugins:
+- name: dropper
+  startup: hot
+  frequency: once
+  tags: [network]
+- name: deathloop
+  startup: hot
+  frequency: once
+  tags: [docker]
+- name: timeburglar
+  startup: hot
+  frequency: once
+"
+            .to_string(),
+        )
+    }
+
+    fn plugin_names(config: &KittypawsConfig) -> Vec<&str> {
+        config
+            .plugins
+            .iter()
+            .map(|plugin| plugin.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_by_tags() {
+        let mut config = tagged_config();
+        config.filter_plugins(&PluginFilter {
+            tags: vec!["network".to_string()],
+            ..Default::default()
+        });
+
+        assert_eq!(plugin_names(&config), vec!["dropper"]);
+    }
+
+    #[test]
+    fn test_filter_only_and_exclude() {
+        let mut config = tagged_config();
+        config.filter_plugins(&PluginFilter {
+            only: vec!["dropper".to_string(), "deathloop".to_string()],
+            exclude: vec!["deathloop".to_string()],
+            ..Default::default()
+        });
+
+        assert_eq!(plugin_names(&config), vec!["dropper"]);
+    }
+
+    #[test]
+    fn test_empty_filter_keeps_everything() {
+        let mut config = tagged_config();
+        config.filter_plugins(&PluginFilter::default());
+
+        assert_eq!(plugin_names(&config).len(), 3);
+    }
 }
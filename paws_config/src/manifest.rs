@@ -0,0 +1,217 @@
+use std::{collections::HashMap, path::Path, path::PathBuf};
+
+use serde::Deserialize;
+
+pub const MANIFEST_FILE_NAME: &str = "plugin.yml";
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginKind {
+    Bash,
+    Python,
+    Executable,
+    Native,
+    Wasm,
+}
+
+/// How a status command prints the status
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusFormat {
+    /// `key=value` lines
+    KeyValue,
+    /// JSON object, nested objects are flattened to `parent.child` keys
+    Json,
+}
+
+/// Command to call for one of the plugin actions.
+/// Relative entrypoint is resolved against the plugin directory.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct EntrypointOptions {
+    pub entrypoint: Option<PathBuf>,
+    pub interpreter: Option<String>,
+    pub args: Option<Vec<String>>,
+    /// Python module in the plugin package, `checks.network`
+    pub module: Option<String>,
+    /// Python function in the module, named after the action by default
+    pub function: Option<String>,
+    /// Status output format, detected by the output if omitted
+    pub format: Option<StatusFormat>,
+}
+
+impl EntrypointOptions {
+    /// Entrypoint, interpreter and module not set for an action are taken from the main one
+    pub fn inherit(&self, main: &EntrypointOptions) -> EntrypointOptions {
+        EntrypointOptions {
+            entrypoint: self.entrypoint.clone().or(main.entrypoint.clone()),
+            interpreter: self.interpreter.clone().or(main.interpreter.clone()),
+            args: self.args.clone(),
+            module: self.module.clone().or(main.module.clone()),
+            function: self.function.clone(),
+            format: self.format,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PluginOptionSpec {
+    pub description: Option<String>,
+    pub required: Option<bool>,
+    pub default: Option<String>,
+}
+
+/// `plugin.yml` in the plugin directory. All fields are optional,
+/// plugins without manifest are detected by their files.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PluginManifest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub kind: Option<PluginKind>,
+    #[serde(flatten)]
+    pub run: EntrypointOptions,
+    pub status: Option<EntrypointOptions>,
+    pub rollback: Option<EntrypointOptions>,
+    /// Ends a fault held after the run, rollback is used if omitted
+    pub revert: Option<EntrypointOptions>,
+    /// Checks before the run that the plugin can do its job
+    pub check: Option<EntrypointOptions>,
+    /// Called once before the first run
+    pub setup: Option<EntrypointOptions>,
+    /// Called once after the last run, even if the run failed or was interrupted
+    pub teardown: Option<EntrypointOptions>,
+    pub options: Option<HashMap<String, PluginOptionSpec>>,
+}
+
+impl PluginManifest {
+    /// Fails listing required options that are not set and have no default
+    pub fn check_options(&self, options: &HashMap<String, String>) -> Result<(), String> {
+        let mut missing: Vec<&str> = self
+            .options
+            .iter()
+            .flatten()
+            .filter(|(name, spec)| {
+                spec.required.unwrap_or(false)
+                    && spec.default.is_none()
+                    && !options.contains_key(*name)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        missing.sort();
+        Err(format!("Missing required options: {}", missing.join(", ")))
+    }
+}
+
+pub fn load_manifest(path_to_plugin: &Path) -> Result<Option<PluginManifest>, String> {
+    let path = path_to_plugin.join(MANIFEST_FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+    serde_yaml::from_str(&contents)
+        .map(Some)
+        .map_err(|err| format!("Invalid {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use super::{EntrypointOptions, PluginKind, PluginManifest, StatusFormat};
+
+    #[test]
+    fn test_executable_manifest() {
+        let manifest: PluginManifest = serde_yaml::from_str(
+            "
+name: dropper
+kind: executable
+entrypoint: bin/dropper
+args: [inject]
+status:
+  args: [status]
+  format: json
+rollback:
+  entrypoint: bin/restore
+teardown:
+  args: [cleanup]
+",
+        )
+        .unwrap();
+
+        assert_eq!(manifest.kind, Some(PluginKind::Executable));
+        assert_eq!(manifest.run.args, Some(vec!["inject".to_string()]));
+
+        let status = manifest.status.unwrap().inherit(&manifest.run);
+        assert_eq!(status.entrypoint, Some(PathBuf::from("bin/dropper")));
+        assert_eq!(status.args, Some(vec!["status".to_string()]));
+        assert_eq!(status.format, Some(StatusFormat::Json));
+
+        let rollback = manifest.rollback.unwrap().inherit(&manifest.run);
+        assert_eq!(rollback.entrypoint, Some(PathBuf::from("bin/restore")));
+        assert_eq!(rollback.args, None);
+
+        assert!(manifest.setup.is_none());
+        let teardown = manifest.teardown.unwrap().inherit(&manifest.run);
+        assert_eq!(teardown.entrypoint, Some(PathBuf::from("bin/dropper")));
+        assert_eq!(teardown.args, Some(vec!["cleanup".to_string()]));
+    }
+
+    #[test]
+    fn test_python_manifest() {
+        let manifest: PluginManifest = serde_yaml::from_str(
+            "
+kind: python
+module: dropper.main
+function: inject
+status:
+  module: dropper.checks
+",
+        )
+        .unwrap();
+
+        let status = manifest.status.unwrap().inherit(&manifest.run);
+        assert_eq!(status.module, Some("dropper.checks".to_string()));
+        assert_eq!(status.function, None);
+
+        let rollback = EntrypointOptions::default().inherit(&manifest.run);
+        assert_eq!(rollback.module, Some("dropper.main".to_string()));
+    }
+
+    #[test]
+    fn test_required_options() {
+        let manifest: PluginManifest = serde_yaml::from_str(
+            "
+options:
+  target:
+    required: true
+  ip:
+    required: true
+  iface:
+    required: true
+    default: eth0
+  comment:
+    description: Optional
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.check_options(&HashMap::new()),
+            Err("Missing required options: ip, target".to_string())
+        );
+        let options = HashMap::from([
+            ("target".to_string(), "db".to_string()),
+            ("ip".to_string(), "10.0.0.5".to_string()),
+        ]);
+        assert!(manifest.check_options(&options).is_ok());
+        assert!(PluginManifest::default()
+            .check_options(&HashMap::new())
+            .is_ok());
+    }
+}
@@ -0,0 +1,232 @@
+use std::str::FromStr;
+
+use serde_yaml::{Mapping, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PathSegment {
+    key: String,
+    selectors: Vec<Selector>,
+}
+
+/// Single `key.path=value` override given on the command line,
+/// e.g. `plugins[dropper].options.ip=10.0.0.5` or `duration=PT10M`.
+///
+/// `[name]` picks the list item with a matching `name` field,
+/// `[0]` picks the list item by its index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOverride {
+    path: Vec<PathSegment>,
+    value: String,
+}
+
+fn parse_segment(segment: &str) -> Result<PathSegment, String> {
+    let (key, mut rest) = match segment.find('[') {
+        Some(pos) => segment.split_at(pos),
+        None => (segment, ""),
+    };
+    if key.is_empty() {
+        return Err(format!("Empty key in path segment `{}`", segment));
+    }
+
+    let mut selectors = Vec::new();
+    while !rest.is_empty() {
+        let Some(end) = rest.find(']') else {
+            return Err(format!("Unclosed `[` in path segment `{}`", segment));
+        };
+        if !rest.starts_with('[') || end == 1 {
+            return Err(format!("Malformed selector in path segment `{}`", segment));
+        }
+        let selector = &rest[1..end];
+        selectors.push(match selector.parse::<usize>() {
+            Ok(index) => Selector::Index(index),
+            Err(_) => Selector::Name(selector.to_string()),
+        });
+        rest = &rest[end + 1..];
+    }
+
+    Ok(PathSegment {
+        key: key.to_string(),
+        selectors,
+    })
+}
+
+impl FromStr for ConfigOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((path, value)) = s.split_once('=') else {
+            return Err(format!("Expected `key.path=value`, got `{}`", s));
+        };
+        let path = path
+            .split('.')
+            .map(parse_segment)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ConfigOverride {
+            path,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Lists and mappings have to be written in flow style, `[a, b]` or `{key: value}`,
+/// anything else is a scalar, e.g. `options.filter=a: b` stays the string `a: b`
+fn parse_value(raw: &str) -> Value {
+    let text = || Value::String(raw.to_string());
+    let structured = raw.starts_with('[') || raw.starts_with('{');
+    match serde_yaml::from_str(raw) {
+        Ok(value @ (Value::Mapping(_) | Value::Sequence(_))) if structured => value,
+        Ok(Value::Mapping(_) | Value::Sequence(_) | Value::Tagged(_)) | Err(_) => text(),
+        Ok(Value::Null) if raw.is_empty() => text(),
+        Ok(value) => value,
+    }
+}
+
+fn select<'a>(
+    node: &'a mut Value,
+    selector: &Selector,
+    key: &str,
+) -> Result<&'a mut Value, String> {
+    let Value::Sequence(items) = node else {
+        return Err(format!("`{}` is not a list", key));
+    };
+    match selector {
+        Selector::Index(index) => {
+            let len = items.len();
+            items
+                .get_mut(*index)
+                .ok_or_else(|| format!("`{}` has {} items, no item at index {}", key, len, index))
+        }
+        Selector::Name(name) => items
+            .iter_mut()
+            .find(|item| item.get("name").and_then(Value::as_str) == Some(name.as_str()))
+            .ok_or_else(|| format!("No item named `{}` in `{}`", name, key)),
+    }
+}
+
+fn child<'a>(node: &'a mut Value, key: &str) -> Result<&'a mut Value, String> {
+    if node.is_null() {
+        *node = Value::Mapping(Mapping::new());
+    }
+    let Value::Mapping(mapping) = node else {
+        return Err(format!(
+            "Can't set `{}` on a value that is not a mapping",
+            key
+        ));
+    };
+    let key = Value::String(key.to_string());
+    if !mapping.contains_key(&key) {
+        mapping.insert(key.clone(), Value::Null);
+    }
+    // unwrap: inserted above if it was missing
+    Ok(mapping.get_mut(&key).unwrap())
+}
+
+impl ConfigOverride {
+    pub fn apply(&self, root: &mut Value) -> Result<(), String> {
+        let mut node = root;
+        for segment in &self.path {
+            node = child(node, &segment.key)?;
+            for selector in &segment.selectors {
+                node = select(node, selector, &segment.key)?;
+            }
+        }
+        *node = parse_value(&self.value);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Value;
+
+    use super::ConfigOverride;
+
+    const CONFIG: &str = "
+duration: PT1M
+plugins:
+- name: dropper
+  options:
+    ip: 127.0.0.1
+- name: deathloop
+";
+
+    fn apply(overrides: &[&str]) -> Value {
+        let mut value: Value = serde_yaml::from_str(CONFIG).unwrap();
+        for item in overrides {
+            item.parse::<ConfigOverride>()
+                .unwrap()
+                .apply(&mut value)
+                .unwrap();
+        }
+        value
+    }
+
+    #[test]
+    fn test_top_level_override() {
+        let value = apply(&["duration=PT10M"]);
+        assert_eq!(value["duration"].as_str(), Some("PT10M"));
+    }
+
+    #[test]
+    fn test_override_by_plugin_name() {
+        let value = apply(&["plugins[dropper].options.ip=10.0.0.5"]);
+        assert_eq!(
+            value["plugins"][0]["options"]["ip"].as_str(),
+            Some("10.0.0.5")
+        );
+    }
+
+    #[test]
+    fn test_override_creates_missing_keys() {
+        let value = apply(&["plugins[1].options.target=db"]);
+        assert_eq!(
+            value["plugins"][1]["options"]["target"].as_str(),
+            Some("db")
+        );
+    }
+
+    #[test]
+    fn test_override_keeps_option_text() {
+        let value = apply(&[
+            "plugins[dropper].options.filter=a: b",
+            "plugins[dropper].options.ports='[80, 443]'",
+            "plugins[dropper].options.ip='10.0.0.5'",
+            "plugins[dropper].options.empty=",
+        ]);
+        let options = &value["plugins"][0]["options"];
+        assert_eq!(options["filter"].as_str(), Some("a: b"));
+        assert_eq!(options["ports"].as_str(), Some("[80, 443]"));
+        assert_eq!(options["ip"].as_str(), Some("10.0.0.5"));
+        assert_eq!(options["empty"].as_str(), Some(""));
+
+        let value = apply(&["plugins[dropper].options={ip: 10.0.0.6}"]);
+        assert_eq!(
+            value["plugins"][0]["options"]["ip"].as_str(),
+            Some("10.0.0.6")
+        );
+    }
+
+    #[test]
+    fn test_override_unknown_plugin() {
+        let mut value: Value = serde_yaml::from_str(CONFIG).unwrap();
+        let result = "plugins[nope].options.ip=1"
+            .parse::<ConfigOverride>()
+            .unwrap()
+            .apply(&mut value);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_malformed_override() {
+        assert!("duration".parse::<ConfigOverride>().is_err());
+        assert!("plugins[dropper.ip=1".parse::<ConfigOverride>().is_err());
+    }
+}
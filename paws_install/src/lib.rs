@@ -4,19 +4,96 @@ use std::{
     ffi::OsStr,
     fs::File,
     io::Write,
-    path::{Component, Components, PathBuf},
+    path::{Component, Components, Path, PathBuf},
 };
 
 use zip::ZipArchive;
 
+mod venv;
+
+pub use venv::{ensure_venv, get_venvs_path, remove_venv};
+
 pub fn get_kittypaws_home() -> PathBuf {
     PathBuf::from(env::var("PAWS_HOME").unwrap_or(unwrap_home_path("~/.kittypaws")))
 }
 
-fn get_plugins_path() -> PathBuf {
+pub fn get_plugins_path() -> PathBuf {
     get_kittypaws_home().join("plugins")
 }
 
+/// State plugin instances keep between runs, a directory per instance
+pub fn get_state_path() -> PathBuf {
+    get_kittypaws_home().join("state")
+}
+
+const PLUGIN_PATH_ENV: &str = "PAWS_PLUGIN_PATH";
+const LOCAL_PLUGINS_DIR: &str = "plugins";
+
+/// Directories plugins are looked up in, earlier ones shadow later ones:
+/// `plugin_dirs` from config, `PAWS_PLUGIN_PATH`, `./plugins`, `${PAWS_HOME}/plugins`
+#[derive(Debug, Clone)]
+pub struct PluginSearchPath {
+    dirs: Vec<PathBuf>,
+}
+
+impl PluginSearchPath {
+    pub fn new(config_dirs: &[PathBuf]) -> Self {
+        let mut candidates: Vec<PathBuf> = config_dirs
+            .iter()
+            .map(|dir| PathBuf::from(unwrap_home_path(&dir.to_string_lossy())))
+            .collect();
+
+        if let Some(env_path) = env::var_os(PLUGIN_PATH_ENV) {
+            candidates
+                .extend(env::split_paths(&env_path).filter(|dir| !dir.as_os_str().is_empty()));
+        }
+        if let Ok(cwd) = env::current_dir() {
+            candidates.push(cwd.join(LOCAL_PLUGINS_DIR));
+        }
+        candidates.push(get_plugins_path());
+
+        let mut dirs: Vec<PathBuf> = vec![];
+        for dir in candidates {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+
+        PluginSearchPath { dirs }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// All directories containing the plugin, the first one is used
+    pub fn find_all(&self, name: &str) -> Vec<PathBuf> {
+        self.dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .filter(|path| path.is_dir())
+            .collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        self.find_all(name).into_iter().next()
+    }
+
+    pub fn warn_if_shadowed(&self, name: &str) {
+        let found = self.find_all(name);
+        if let Some((used, shadowed)) = found.split_first() {
+            for path in shadowed {
+                println!(
+                    "! WARNING: plugin {} at {} shadows {}",
+                    name,
+                    used.display(),
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
 fn get_plugin_path(plugin_name: &str) -> PathBuf {
     get_plugins_path().join(plugin_name)
 }
@@ -30,21 +107,19 @@ pub fn remove_plugin(name: String) -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Removing {}...", name);
 
+    remove_venv(&name, &path)?;
     std::fs::remove_dir_all(get_plugin_path(&name))?;
     println!("Plugin {} removed!", name);
     Ok(())
 }
 
-fn get_all_plugins() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let path: PathBuf = get_kittypaws_home().join("plugins");
+fn get_all_plugins(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     if !path.exists() {
         return Ok(vec![]);
     }
     let mut plugins: Vec<String> = vec![];
 
-    println!("{:?}", path);
-    for path in path.read_dir().unwrap() {
-        println!("inside path {:?}", path);
+    for path in path.read_dir()? {
         let path = path?.path();
 
         if !path.is_dir() {
@@ -57,11 +132,23 @@ fn get_all_plugins() -> Result<Vec<String>, Box<dyn std::error::Error>> {
     Ok(plugins)
 }
 
-pub fn list_plugins() -> Result<(), Box<dyn std::error::Error>> {
+pub fn list_plugins(search_path: &PluginSearchPath) -> Result<(), Box<dyn std::error::Error>> {
     println!("Installed plugins:");
 
-    for plugin in get_all_plugins()?.iter() {
-        println!("- {}", plugin);
+    let mut seen: Vec<String> = vec![];
+    for dir in search_path.dirs() {
+        if !dir.is_dir() {
+            continue;
+        }
+        println!("{}:", dir.display());
+        for name in get_all_plugins(dir)? {
+            if seen.contains(&name) {
+                println!("- {} (shadowed)", name);
+            } else {
+                println!("- {}", name);
+                seen.push(name);
+            }
+        }
     }
 
     Ok(())
@@ -118,7 +205,7 @@ pub fn install_from_github(
         let components: Components = file_name.components();
         let mut components: Vec<_> = components.into_iter().collect();
         components.remove(0);
-        components.insert(0, Component::Normal(&OsStr::new(&plugin_save_name)));
+        components.insert(0, Component::Normal(OsStr::new(&plugin_save_name)));
 
         let file_path = PathBuf::from_iter(components);
 
@@ -129,7 +216,7 @@ pub fn install_from_github(
         } else {
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    std::fs::create_dir_all(&p)?;
+                    std::fs::create_dir_all(p)?;
                 }
             }
             let mut outfile = File::create(&outpath)?;
@@ -138,6 +225,7 @@ pub fn install_from_github(
     }
 
     std::fs::remove_file(temp_file_path).ok();
+    ensure_venv(&plugin_save_name, &destination.join(&plugin_save_name))?;
     println!("Installed {} as {}", repo_spec, plugin_save_name);
 
     Ok(())
@@ -164,13 +252,17 @@ mod tests {
     use envtestkit::lock::lock_test;
     use tempfile::{tempdir, TempDir};
 
-    use crate::{get_all_plugins, get_plugins_path, install_from_github, remove_plugin};
+    use crate::{
+        get_all_plugins, get_plugins_path, install_from_github, remove_plugin, PluginSearchPath,
+        PLUGIN_PATH_ENV,
+    };
 
     const TEST_PLUGIN: &str = "test_plugin";
 
     fn setup_test() -> TempDir {
         let tmp_dir = tempdir().unwrap();
         env::set_var("PAWS_HOME", tmp_dir.path());
+        env::remove_var(PLUGIN_PATH_ENV);
         tmp_dir
     }
 
@@ -179,7 +271,7 @@ mod tests {
         let _lock = lock_test();
         let _path = setup_test();
 
-        let listed_plugins = get_all_plugins().unwrap();
+        let listed_plugins = get_all_plugins(&get_plugins_path()).unwrap();
 
         assert!(listed_plugins.is_empty());
     }
@@ -190,7 +282,7 @@ mod tests {
         let _path = setup_test();
         std::fs::create_dir_all(get_plugins_path()).unwrap();
 
-        let listed_plugins = get_all_plugins().unwrap();
+        let listed_plugins = get_all_plugins(&get_plugins_path()).unwrap();
 
         assert!(listed_plugins.is_empty());
     }
@@ -207,7 +299,7 @@ mod tests {
         )
         .unwrap();
 
-        let listed_plugins = get_all_plugins().unwrap();
+        let listed_plugins = get_all_plugins(&get_plugins_path()).unwrap();
 
         assert!(listed_plugins.contains(&TEST_PLUGIN.to_string()));
     }
@@ -220,7 +312,45 @@ mod tests {
 
         remove_plugin(TEST_PLUGIN.to_string()).unwrap();
 
-        let listed_plugins = get_all_plugins().unwrap();
+        let listed_plugins = get_all_plugins(&get_plugins_path()).unwrap();
         assert!(!listed_plugins.contains(&TEST_PLUGIN.to_string()));
     }
+
+    #[test]
+    fn test_search_path_falls_back_to_home() {
+        let _lock = lock_test();
+        let _path = setup_test();
+        std::fs::create_dir_all(get_plugins_path().join(TEST_PLUGIN)).unwrap();
+
+        let search_path = PluginSearchPath::new(&[]);
+
+        assert_eq!(
+            search_path.find(TEST_PLUGIN),
+            Some(get_plugins_path().join(TEST_PLUGIN))
+        );
+        assert_eq!(search_path.find("missing_plugin"), None);
+    }
+
+    #[test]
+    fn test_search_path_precedence() {
+        let _lock = lock_test();
+        let path = setup_test();
+        let config_dir = path.path().join("from_config");
+        let env_dir = path.path().join("from_env");
+        for dir in [&config_dir, &env_dir, &get_plugins_path()] {
+            std::fs::create_dir_all(dir.join(TEST_PLUGIN)).unwrap();
+        }
+        env::set_var(PLUGIN_PATH_ENV, &env_dir);
+
+        let from_env = PluginSearchPath::new(&[]);
+        let from_config = PluginSearchPath::new(std::slice::from_ref(&config_dir));
+
+        assert_eq!(from_env.find(TEST_PLUGIN), Some(env_dir.join(TEST_PLUGIN)));
+        assert_eq!(from_env.find_all(TEST_PLUGIN).len(), 2);
+        assert_eq!(
+            from_config.find(TEST_PLUGIN),
+            Some(config_dir.join(TEST_PLUGIN))
+        );
+        assert_eq!(from_config.find_all(TEST_PLUGIN).len(), 3);
+    }
 }
@@ -0,0 +1,177 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::get_kittypaws_home;
+
+/// Interpreter virtualenvs are created with, `python3` by default
+const BASE_PYTHON_ENV: &str = "PAWS_PYTHON";
+const REQUIREMENTS_FILES: [&str; 2] = ["requirements.txt", "pyproject.toml"];
+/// Written into the virtualenv once dependencies are installed
+const INSTALLED_MARKER: &str = ".paws-installed";
+
+pub fn get_venvs_path() -> PathBuf {
+    get_kittypaws_home().join("venvs")
+}
+
+/// FNV-1a, stable between builds unlike the std hasher
+fn path_hash(path: &Path) -> u64 {
+    path.as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Keyed by where the plugin is, plugins with the same name in different dirs
+/// don't share a virtualenv. The name only makes the directory recognizable.
+pub fn get_venv_path(plugin_name: &str, path_to_plugin: &Path) -> Result<PathBuf, String> {
+    let resolved = path_to_plugin
+        .canonicalize()
+        .map_err(|err| format!("Can't resolve {}: {}", path_to_plugin.display(), err))?;
+    Ok(get_venvs_path().join(format!("{}-{:016x}", plugin_name, path_hash(&resolved))))
+}
+
+/// `requirements.txt` or `pyproject.toml` of a Python plugin
+pub fn plugin_requirements(path_to_plugin: &Path) -> Option<PathBuf> {
+    REQUIREMENTS_FILES
+        .iter()
+        .map(|file| path_to_plugin.join(file))
+        .find(|path| path.is_file())
+}
+
+pub fn venv_python(venv: &Path) -> PathBuf {
+    if cfg!(windows) {
+        venv.join("Scripts").join("python.exe")
+    } else {
+        venv.join("bin").join("python")
+    }
+}
+
+fn is_up_to_date(venv: &Path, requirements: &Path) -> bool {
+    let modified = |path: &Path| path.metadata().and_then(|meta| meta.modified()).ok();
+
+    match (
+        modified(&venv.join(INSTALLED_MARKER)),
+        modified(requirements),
+    ) {
+        (Some(installed), Some(changed)) => installed >= changed,
+        _ => false,
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let status = command
+        .status()
+        .map_err(|err| format!("Failed to execute {:?}: {}", command, err))?;
+    if !status.success() {
+        return Err(format!("{:?} {}", command, status));
+    }
+
+    Ok(())
+}
+
+/// Creates a virtualenv for a plugin with its dependencies installed
+/// if the plugin declares any. Returns the interpreter to run the plugin with.
+pub fn ensure_venv(plugin_name: &str, path_to_plugin: &Path) -> Result<Option<PathBuf>, String> {
+    let Some(requirements) = plugin_requirements(path_to_plugin) else {
+        return Ok(None);
+    };
+    let venv = get_venv_path(plugin_name, path_to_plugin)?;
+    let python = venv_python(&venv);
+    if python.exists() && is_up_to_date(&venv, &requirements) {
+        return Ok(Some(python));
+    }
+
+    println!(
+        "Creating virtualenv for {} at {}...",
+        plugin_name,
+        venv.display()
+    );
+    let base_python = env::var(BASE_PYTHON_ENV).unwrap_or("python3".to_string());
+    run(Command::new(base_python).args(["-m", "venv"]).arg(&venv))?;
+
+    println!("Installing dependencies from {}...", requirements.display());
+    let mut pip = Command::new(&python);
+    pip.args(["-m", "pip", "install", "--disable-pip-version-check"]);
+    if requirements.ends_with("requirements.txt") {
+        pip.arg("-r").arg(&requirements);
+    } else {
+        pip.arg(path_to_plugin);
+    }
+    run(&mut pip)?;
+
+    std::fs::write(venv.join(INSTALLED_MARKER), "")
+        .map_err(|err| format!("Can't write to {}: {}", venv.display(), err))?;
+
+    Ok(Some(python))
+}
+
+/// Has to be called before the plugin itself is removed
+pub fn remove_venv(plugin_name: &str, path_to_plugin: &Path) -> Result<(), String> {
+    let venv = get_venv_path(plugin_name, path_to_plugin)?;
+    if venv.exists() {
+        std::fs::remove_dir_all(&venv)
+            .map_err(|err| format!("Can't remove {}: {}", venv.display(), err))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use envtestkit::lock::lock_test;
+    use tempfile::tempdir;
+
+    use super::{ensure_venv, get_venv_path, remove_venv, INSTALLED_MARKER};
+
+    #[test]
+    fn test_no_requirements() {
+        let plugin = tempdir().unwrap();
+
+        assert_eq!(ensure_venv("plain", plugin.path()), Ok(None));
+    }
+
+    #[test]
+    fn test_venv_created_once() {
+        let _lock = lock_test();
+        let home = tempdir().unwrap();
+        env::set_var("PAWS_HOME", home.path());
+        let plugin = tempdir().unwrap();
+        std::fs::write(plugin.path().join("requirements.txt"), "").unwrap();
+
+        let python = ensure_venv("with_deps", plugin.path()).unwrap().unwrap();
+        assert!(python.exists());
+
+        let marker = get_venv_path("with_deps", plugin.path())
+            .unwrap()
+            .join(INSTALLED_MARKER);
+        let installed = marker.metadata().unwrap().modified().unwrap();
+        assert_eq!(ensure_venv("with_deps", plugin.path()), Ok(Some(python)));
+        assert_eq!(marker.metadata().unwrap().modified().unwrap(), installed);
+
+        remove_venv("with_deps", plugin.path()).unwrap();
+        assert!(!get_venv_path("with_deps", plugin.path()).unwrap().exists());
+    }
+
+    #[test]
+    fn test_venv_per_plugin_path() {
+        let plugins = tempdir().unwrap();
+        let first = plugins.path().join("a/dropper");
+        let second = plugins.path().join("b/dropper");
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+
+        let venv = get_venv_path("dropper", &first).unwrap();
+        assert_ne!(venv, get_venv_path("dropper", &second).unwrap());
+        assert_eq!(
+            venv,
+            get_venv_path("dropper", &plugins.path().join("b/../a/dropper")).unwrap()
+        );
+    }
+}
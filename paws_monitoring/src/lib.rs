@@ -1,13 +1,21 @@
+mod reporter;
 pub mod telegraf;
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+pub use reporter::Reporter;
 
 use telegraf::TelegrafClient;
 
 #[derive(Debug)]
 pub enum StatusValue {
+    Bool(bool),
     Int(i64),
+    UInt(u64),
     Float(f64),
     String(String),
+    Duration(Duration),
+    Timestamp(SystemTime),
 }
 
 pub enum MonitoringBackend {
@@ -20,6 +28,13 @@ pub trait MetricSender: Send {
         tags: HashMap<String, String>,
         fields: HashMap<String, StatusValue>,
     ) -> Result<(), String>;
+
+    /// Something that happened during the run, stored apart from the metrics
+    fn send_event(
+        &mut self,
+        tags: HashMap<String, String>,
+        fields: HashMap<String, StatusValue>,
+    ) -> Result<(), String>;
 }
 
 pub fn init_monitoring_backend(option: MonitoringBackend, dsn: &str) -> Box<dyn MetricSender> {
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::{MetricSender, StatusValue};
+
+enum Report {
+    Metric(HashMap<String, String>, HashMap<String, StatusValue>),
+    Event(HashMap<String, String>, HashMap<String, StatusValue>),
+}
+
+/// Cloneable handle sending metrics and events to one monitoring backend
+/// from any thread. Reports are dropped if monitoring is not configured.
+#[derive(Clone, Default)]
+pub struct Reporter {
+    sender: Option<Sender<Report>>,
+}
+
+impl Reporter {
+    /// Starts a thread writing reports to the backend until all reporters are dropped
+    pub fn start(mut backend: Box<dyn MetricSender>) -> (Reporter, JoinHandle<()>) {
+        let (sender, receiver) = channel();
+        let handle = thread::spawn(move || {
+            for report in receiver {
+                let result = match report {
+                    Report::Metric(tags, fields) => backend.send_metric(tags, fields),
+                    Report::Event(tags, fields) => backend.send_event(tags, fields),
+                };
+                if let Err(err) = result {
+                    println!("Can't send metric: {}", err);
+                }
+            }
+        });
+
+        (
+            Reporter {
+                sender: Some(sender),
+            },
+            handle,
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn metric(&self, tags: HashMap<String, String>, fields: HashMap<String, StatusValue>) {
+        self.send(Report::Metric(tags, fields));
+    }
+
+    pub fn event(&self, tags: HashMap<String, String>, fields: HashMap<String, StatusValue>) {
+        self.send(Report::Event(tags, fields));
+    }
+
+    fn send(&self, report: Report) {
+        if let Some(sender) = &self.sender {
+            // Backend thread only stops when all senders are gone
+            let _ = sender.send(report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::Reporter;
+    use crate::{MetricSender, StatusValue};
+
+    #[derive(Default, Clone)]
+    struct Recorder {
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MetricSender for Recorder {
+        fn send_metric(
+            &mut self,
+            _tags: HashMap<String, String>,
+            fields: HashMap<String, StatusValue>,
+        ) -> Result<(), String> {
+            self.sent.lock().unwrap().extend(fields.into_keys());
+            Ok(())
+        }
+
+        fn send_event(
+            &mut self,
+            tags: HashMap<String, String>,
+            _fields: HashMap<String, StatusValue>,
+        ) -> Result<(), String> {
+            self.sent.lock().unwrap().extend(tags.into_values());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reports_reach_backend() {
+        let recorder = Recorder::default();
+        let (reporter, handle) = Reporter::start(Box::new(recorder.clone()));
+
+        let from_thread = reporter.clone();
+        std::thread::spawn(move || {
+            from_thread.metric(
+                HashMap::new(),
+                HashMap::from([("errors".to_string(), StatusValue::Int(1))]),
+            )
+        })
+        .join()
+        .unwrap();
+        reporter.event(
+            HashMap::from([("event".to_string(), "restarted".to_string())]),
+            HashMap::new(),
+        );
+        drop(reporter);
+        handle.join().unwrap();
+
+        assert_eq!(*recorder.sent.lock().unwrap(), vec!["errors", "restarted"]);
+        assert!(!Reporter::default().is_enabled());
+    }
+}
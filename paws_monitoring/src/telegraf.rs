@@ -3,6 +3,8 @@ use telegraf::{
     FieldData,
 };
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::{MetricSender, StatusValue};
 
 pub struct TelegrafClient {
@@ -18,19 +20,34 @@ impl TelegrafClient {
     }
 }
 
+/// Milliseconds since the Unix epoch, negative for earlier times
+fn unix_millis(time: &SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+/// Durations are sent as float seconds and timestamps as Unix milliseconds,
+/// Influx has no field types for them
 impl From<&StatusValue> for FieldData {
     fn from(value: &StatusValue) -> Self {
         match value {
+            StatusValue::Bool(val) => FieldData::Boolean(val.to_owned()),
             StatusValue::Int(val) => FieldData::Number(val.to_owned()),
+            StatusValue::UInt(val) => FieldData::UNumber(val.to_owned()),
             StatusValue::Float(val) => FieldData::Float(val.to_owned()),
             StatusValue::String(val) => FieldData::Str(val.to_owned()),
+            StatusValue::Duration(val) => FieldData::Float(val.as_secs_f64()),
+            StatusValue::Timestamp(val) => FieldData::Number(unix_millis(val)),
         }
     }
 }
 
-impl MetricSender for TelegrafClient {
-    fn send_metric(
+impl TelegrafClient {
+    fn write(
         &mut self,
+        measurement: &str,
         tags: std::collections::HashMap<String, String>,
         fields: std::collections::HashMap<String, crate::StatusValue>,
     ) -> Result<(), String> {
@@ -49,7 +66,7 @@ impl MetricSender for TelegrafClient {
             })
             .collect();
         let point = telegraf::Point {
-            measurement: "kittypaws".to_string(),
+            measurement: measurement.to_string(),
             tags,
             fields,
             timestamp: None,
@@ -60,3 +77,53 @@ impl MetricSender for TelegrafClient {
         Ok(())
     }
 }
+
+impl MetricSender for TelegrafClient {
+    fn send_metric(
+        &mut self,
+        tags: std::collections::HashMap<String, String>,
+        fields: std::collections::HashMap<String, crate::StatusValue>,
+    ) -> Result<(), String> {
+        self.write("kittypaws", tags, fields)
+    }
+
+    fn send_event(
+        &mut self,
+        tags: std::collections::HashMap<String, String>,
+        fields: std::collections::HashMap<String, crate::StatusValue>,
+    ) -> Result<(), String> {
+        self.write("kittypaws_events", tags, fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use telegraf::FieldData;
+
+    use crate::StatusValue;
+
+    #[test]
+    fn test_field_types() {
+        let field = |value: StatusValue| FieldData::from(&value);
+
+        assert_eq!(field(StatusValue::Bool(true)), FieldData::Boolean(true));
+        assert_eq!(
+            field(StatusValue::UInt(u64::MAX)),
+            FieldData::UNumber(u64::MAX)
+        );
+        assert_eq!(
+            field(StatusValue::Duration(Duration::from_millis(1500))),
+            FieldData::Float(1.5)
+        );
+        assert_eq!(
+            field(StatusValue::Timestamp(UNIX_EPOCH + Duration::from_secs(2))),
+            FieldData::Number(2000)
+        );
+        assert_eq!(
+            field(StatusValue::Timestamp(UNIX_EPOCH - Duration::from_secs(2))),
+            FieldData::Number(-2000)
+        );
+    }
+}
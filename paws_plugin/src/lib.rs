@@ -0,0 +1,317 @@
+//! Helpers for writing native Kittypaws plugins in Rust.
+//!
+//! A native plugin is a shared library (`plugin.so`) exporting the C ABI below.
+//! Build the crate with `crate-type = ["cdylib"]`, implement [`Plugin`] and export it with [`export_plugin!`]:
+//!
+//! ```ignore
+//! #[derive(Default)]
+//! struct Dropper;
+//!
+//! impl paws_plugin::Plugin for Dropper {
+//!     fn run(&mut self, options: &paws_plugin::Options) -> Result<(), String> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! paws_plugin::export_plugin!(Dropper);
+//! ```
+//!
+//! ABI, every call returns `0` on success:
+//!
+//! ```c
+//! uint32_t paws_abi_version(void);
+//! void*    paws_init(void);
+//! int32_t  paws_run(void* plugin, const PawsOption* options, size_t len, char** error);
+//! int32_t  paws_status(void* plugin, const PawsOption* options, size_t len, char** output);
+//! int32_t  paws_rollback(void* plugin, const PawsOption* options, size_t len, char** error);
+//! void     paws_free_string(char* string);
+//! void     paws_free(void* plugin);
+//! ```
+//!
+//! `paws_status` writes `key=value` lines to `output` on success.
+//! Strings written to `error` and `output` are owned by the plugin and freed with `paws_free_string`.
+//! Calls to one plugin instance are never made concurrently.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+pub const ABI_VERSION: u32 = 1;
+
+pub type Options = HashMap<String, String>;
+
+#[repr(C)]
+pub struct PawsOption {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+pub trait Plugin: Default {
+    fn run(&mut self, options: &Options) -> Result<(), String>;
+
+    fn status(&mut self, _options: &Options) -> Result<HashMap<String, String>, String> {
+        Err("Plugin has no status".to_string())
+    }
+
+    fn rollback(&mut self, _options: &Options) -> Result<(), String> {
+        Err("Plugin has no rollback".to_string())
+    }
+}
+
+/// # Safety
+/// `options` must point to `len` valid pairs of nul-terminated strings
+pub unsafe fn read_options(options: *const PawsOption, len: usize) -> Options {
+    if options.is_null() {
+        return Options::new();
+    }
+
+    std::slice::from_raw_parts(options, len)
+        .iter()
+        .map(|option| {
+            (
+                CStr::from_ptr(option.key).to_string_lossy().to_string(),
+                CStr::from_ptr(option.value).to_string_lossy().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn to_c_string(value: String) -> *mut c_char {
+    CString::new(value.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+unsafe fn write_string(out: *mut *mut c_char, value: String) {
+    if !out.is_null() {
+        *out = to_c_string(value);
+    }
+}
+
+fn catch<T>(call: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|_| Err("Plugin panicked".to_string()))
+}
+
+#[doc(hidden)]
+pub fn __init<P: Plugin>() -> *mut c_void {
+    match catch_unwind(P::default) {
+        Ok(plugin) => Box::into_raw(Box::new(plugin)) as *mut c_void,
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn __run<P: Plugin>(
+    plugin: *mut c_void,
+    options: *const PawsOption,
+    len: usize,
+    error: *mut *mut c_char,
+) -> i32 {
+    let plugin = &mut *(plugin as *mut P);
+    let options = read_options(options, len);
+    match catch(|| plugin.run(&options)) {
+        Ok(()) => 0,
+        Err(err) => {
+            write_string(error, err);
+            1
+        }
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn __status<P: Plugin>(
+    plugin: *mut c_void,
+    options: *const PawsOption,
+    len: usize,
+    output: *mut *mut c_char,
+) -> i32 {
+    let plugin = &mut *(plugin as *mut P);
+    let options = read_options(options, len);
+    match catch(|| plugin.status(&options)) {
+        Ok(status) => {
+            let lines: Vec<String> = status
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            write_string(output, lines.join("\n"));
+            0
+        }
+        Err(err) => {
+            write_string(output, err);
+            1
+        }
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn __rollback<P: Plugin>(
+    plugin: *mut c_void,
+    options: *const PawsOption,
+    len: usize,
+    error: *mut *mut c_char,
+) -> i32 {
+    let plugin = &mut *(plugin as *mut P);
+    let options = read_options(options, len);
+    match catch(|| plugin.rollback(&options)) {
+        Ok(()) => 0,
+        Err(err) => {
+            write_string(error, err);
+            1
+        }
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn __free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[doc(hidden)]
+pub unsafe fn __free<P: Plugin>(plugin: *mut c_void) {
+    if !plugin.is_null() {
+        drop(Box::from_raw(plugin as *mut P));
+    }
+}
+
+/// Exports the Kittypaws plugin ABI for a type implementing [`Plugin`]
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:ty) => {
+        #[no_mangle]
+        pub extern "C" fn paws_abi_version() -> u32 {
+            $crate::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn paws_init() -> *mut ::std::ffi::c_void {
+            $crate::__init::<$plugin>()
+        }
+
+        /// # Safety
+        /// Called by Kittypaws according to the plugin ABI
+        #[no_mangle]
+        pub unsafe extern "C" fn paws_run(
+            plugin: *mut ::std::ffi::c_void,
+            options: *const $crate::PawsOption,
+            len: usize,
+            error: *mut *mut ::std::ffi::c_char,
+        ) -> i32 {
+            $crate::__run::<$plugin>(plugin, options, len, error)
+        }
+
+        /// # Safety
+        /// Called by Kittypaws according to the plugin ABI
+        #[no_mangle]
+        pub unsafe extern "C" fn paws_status(
+            plugin: *mut ::std::ffi::c_void,
+            options: *const $crate::PawsOption,
+            len: usize,
+            output: *mut *mut ::std::ffi::c_char,
+        ) -> i32 {
+            $crate::__status::<$plugin>(plugin, options, len, output)
+        }
+
+        /// # Safety
+        /// Called by Kittypaws according to the plugin ABI
+        #[no_mangle]
+        pub unsafe extern "C" fn paws_rollback(
+            plugin: *mut ::std::ffi::c_void,
+            options: *const $crate::PawsOption,
+            len: usize,
+            error: *mut *mut ::std::ffi::c_char,
+        ) -> i32 {
+            $crate::__rollback::<$plugin>(plugin, options, len, error)
+        }
+
+        /// # Safety
+        /// Called by Kittypaws according to the plugin ABI
+        #[no_mangle]
+        pub unsafe extern "C" fn paws_free_string(string: *mut ::std::ffi::c_char) {
+            $crate::__free_string(string)
+        }
+
+        /// # Safety
+        /// Called by Kittypaws according to the plugin ABI
+        #[no_mangle]
+        pub unsafe extern "C" fn paws_free(plugin: *mut ::std::ffi::c_void) {
+            $crate::__free::<$plugin>(plugin)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ffi::{c_char, CStr, CString};
+
+    use crate::{Options, PawsOption, Plugin};
+
+    #[derive(Default)]
+    struct Counter {
+        runs: u32,
+    }
+
+    impl Plugin for Counter {
+        fn run(&mut self, options: &Options) -> Result<(), String> {
+            if options.get("fail").is_some() {
+                return Err("asked to fail".to_string());
+            }
+            self.runs += 1;
+            Ok(())
+        }
+
+        fn status(&mut self, _options: &Options) -> Result<HashMap<String, String>, String> {
+            Ok(HashMap::from([("runs".to_string(), self.runs.to_string())]))
+        }
+    }
+
+    export_plugin!(Counter);
+
+    fn take_string(string: *mut c_char) -> String {
+        let value = unsafe { CStr::from_ptr(string) }
+            .to_string_lossy()
+            .to_string();
+        unsafe { paws_free_string(string) };
+        value
+    }
+
+    #[test]
+    fn test_exported_abi() {
+        assert_eq!(paws_abi_version(), crate::ABI_VERSION);
+
+        let plugin = paws_init();
+        let mut output: *mut c_char = std::ptr::null_mut();
+
+        assert_eq!(
+            unsafe { paws_run(plugin, std::ptr::null(), 0, &mut output) },
+            0
+        );
+        assert_eq!(
+            unsafe { paws_status(plugin, std::ptr::null(), 0, &mut output) },
+            0
+        );
+        assert_eq!(take_string(output), "runs=1");
+
+        let key = CString::new("fail").unwrap();
+        let value = CString::new("yes").unwrap();
+        let options = [PawsOption {
+            key: key.as_ptr(),
+            value: value.as_ptr(),
+        }];
+        assert_eq!(
+            unsafe { paws_run(plugin, options.as_ptr(), options.len(), &mut output) },
+            1
+        );
+        assert_eq!(take_string(output), "asked to fail");
+
+        assert_eq!(
+            unsafe { paws_rollback(plugin, std::ptr::null(), 0, &mut output) },
+            1
+        );
+        assert_eq!(take_string(output), "Plugin has no rollback");
+
+        unsafe { paws_free(plugin) };
+    }
+}
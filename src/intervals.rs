@@ -1,23 +1,44 @@
 use rand::Rng;
 use std::thread;
-use std::time::Duration;
-use paws_config::FrequencyOptions;
+use std::time::{Duration, Instant};
+use paws_config::{Duration as ConfigDuration, FrequencyOptions, HoldOptions, RandomRange};
 
+use crate::shutdown;
 
+/// How often waiting plugins check if the run was interrupted
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sleeps for the duration or until the run is interrupted
 pub fn wait_duration(duration: Duration) {
-    thread::sleep(duration);
+    let until = Instant::now() + duration;
+    while !shutdown::is_requested() {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(SHUTDOWN_POLL_INTERVAL));
+    }
 }
 
 fn get_wait_time(frequency: &FrequencyOptions) -> Option<Duration> {
     match &frequency {
         FrequencyOptions::Once => None,
         FrequencyOptions::Fixed(duration) => Some(duration.as_std()),
-        FrequencyOptions::Random(range) => Some(
-            Duration::from_secs(
-            rand::thread_rng()
-                .gen_range(range.min.as_std().as_secs()..range.max.as_std().as_secs())
-            )
-        )
+        FrequencyOptions::Random(range) => Some(random_duration(range))
+    }
+}
+
+/// Bounds are inclusive, so `min == max` and sub-second ranges work too
+fn random_duration(range: &RandomRange<ConfigDuration>) -> Duration {
+    let min = range.min.as_std().as_millis() as u64;
+    let max = range.max.as_std().as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(min..=max))
+}
+
+pub fn hold_duration(hold: &HoldOptions) -> Duration {
+    match hold {
+        HoldOptions::Fixed(duration) => duration.as_std(),
+        HoldOptions::Random(range) => random_duration(range),
     }
 }
 
@@ -30,3 +51,27 @@ pub fn time_till_next_run(frequency: &FrequencyOptions) -> Option<Duration> {
         None => None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use paws_config::HoldOptions;
+
+    use super::hold_duration;
+
+    fn hold(yaml: &str) -> HoldOptions {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_hold_duration_bounds() {
+        assert_eq!(
+            hold_duration(&hold("{min: PT30S, max: PT30S}")),
+            Duration::from_secs(30)
+        );
+
+        let short = hold_duration(&hold("{min: PT0.1S, max: PT0.5S}"));
+        assert!(short >= Duration::from_millis(100) && short <= Duration::from_millis(500));
+    }
+}
@@ -1,13 +1,17 @@
 mod intervals;
 mod plug;
+mod scaffold;
 mod settings;
+mod shutdown;
 mod stdout_styling;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use paws_install::{list_plugins, install_from_github, remove_plugin, get_kittypaws_home};
-use plug::start_main_loop;
-use paws_config::load_config;
+use paws_install::{list_plugins, install_from_github, remove_plugin, get_kittypaws_home, get_plugins_path, PluginSearchPath};
+use plug::{exec_plugin, start_main_loop, ExecAction};
+use scaffold::{init_config, new_plugin, PluginTemplate};
+use paws_config::{load_config_with_overrides, ConfigOverride, PluginFilter};
 
 use clap::{Parser, Subcommand};
 
@@ -17,15 +21,67 @@ fn get_default_config_path() -> PathBuf {
     get_kittypaws_home().join(DEFAULT_CONFIG_FILE_NAME)
 }
 
+fn parse_plugin_option(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("Expected `key=value`, got `{}`", value)),
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     Run {
         #[arg(long = "config")]
-        config: Option<PathBuf>
+        config: Option<PathBuf>,
+        /// Override a config value, e.g. `--set plugins[dropper].options.ip=10.0.0.5`
+        #[arg(long = "set", value_name = "KEY.PATH=VALUE")]
+        overrides: Vec<ConfigOverride>,
+        /// Run only plugins with these names
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<String>,
+        /// Skip plugins with these names
+        #[arg(long, value_delimiter = ',')]
+        exclude: Vec<String>,
+        /// Run only plugins having any of these tags
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Start the run even if some plugins fail the preflight check
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Invoke an installed plugin once without a config file
+    Exec {
+        plugin: String,
+        /// Plugin option, can be repeated
+        #[arg(long = "opt", value_name = "KEY=VALUE", value_parser = parse_plugin_option)]
+        options: Vec<(String, String)>,
+        /// Fetch plugin status instead of running it
+        #[arg(long, conflicts_with = "rollback")]
+        status: bool,
+        /// Roll back whatever the plugin did
+        #[arg(long)]
+        rollback: bool,
     },
 
     List,
 
+    /// Write a starter config to the default config path
+    Init {
+        /// Overwrite existing config
+        #[arg(long)]
+        force: bool,
+    },
+    /// Scaffold a new plugin
+    New {
+        name: String,
+        #[arg(long, value_enum, default_value = "bash")]
+        lang: PluginTemplate,
+        /// Directory to create the plugin in, installed plugins directory by default
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+
     Uninstall {
         name: String,
     },
@@ -50,11 +106,52 @@ fn main() {
     let args = CliArguments::parse();
 
     match args.command {
-        Command::Run { config } => {
-            let config = load_config(config.unwrap_or(get_default_config_path()));
-            start_main_loop(config);
+        Command::Run {
+            config,
+            overrides,
+            only,
+            exclude,
+            tags,
+            force,
+        } => {
+            let mut config = load_config_with_overrides(
+                config.unwrap_or(get_default_config_path()),
+                &overrides,
+            );
+            config.filter_plugins(&PluginFilter { only, exclude, tags });
+            if config.plugins.is_empty() {
+                println!("No plugins left to run after filtering");
+                return;
+            }
+            if let Err(err) = start_main_loop(config, force) {
+                println!("{}", err);
+                std::process::exit(1);
+            }
         },
-        Command::List => list_plugins().unwrap(),
+        Command::Exec {
+            plugin,
+            options,
+            status,
+            rollback,
+        } => {
+            let action = match (status, rollback) {
+                (true, _) => ExecAction::Status,
+                (_, true) => ExecAction::Rollback,
+                _ => ExecAction::Run,
+            };
+            let options: HashMap<String, String> = options.into_iter().collect();
+
+            if let Err(err) = exec_plugin(&plugin, &options, action) {
+                println!("Exit status: failed ({})", err);
+                std::process::exit(1);
+            }
+            println!("Exit status: ok");
+        }
+        Command::List => list_plugins(&PluginSearchPath::new(&[])).unwrap(),
+        Command::Init { force } => init_config(&get_default_config_path(), force).unwrap(),
+        Command::New { name, lang, path } => {
+            new_plugin(&name, lang, &path.unwrap_or(get_plugins_path())).unwrap();
+        }
         Command::Install { github_path, branch, save_as } => install_from_github(&github_path, &branch, save_as).unwrap(),
         Command::Uninstall { name } => remove_plugin(name).unwrap(),
     }
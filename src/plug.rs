@@ -1,45 +1,127 @@
-use std::fs;
 mod bash_plugin;
+mod builtin_probe;
+mod circuit_breaker;
+mod command_plugin;
+mod context;
+mod executable_plugin;
+mod native_plugin;
 mod python_plugin;
+mod python_process_plugin;
+mod recovery;
+mod steady_state;
+#[cfg(feature = "wasm")]
+mod wasm_plugin;
 use bash_plugin::load as load_sh_plugin;
 use chrono::{DateTime, Utc};
-use paws_monitoring::{init_monitoring_backend, MetricSender};
+use circuit_breaker::CircuitBreaker;
+pub use context::PluginContext;
+use executable_plugin::load as load_exec_plugin;
+use native_plugin::{library_file_name, load as load_native_plugin};
+use paws_install::{ensure_venv, PluginSearchPath};
+use paws_monitoring::{init_monitoring_backend, Reporter, StatusValue};
 use python_plugin::load as load_py_plugin;
+use python_process_plugin::load as load_py_process_plugin;
+use recovery::Recovery;
+use steady_state::SteadyState;
 use uuid::Uuid;
+#[cfg(feature = "wasm")]
+use wasm_plugin::load as load_wasm_plugin;
 
-use crate::intervals::{time_till_next_run, wait_duration};
+use crate::intervals::{hold_duration, time_till_next_run, wait_duration};
+use crate::shutdown;
 use crate::stdout_styling::style_line;
 use paws_config::{
-    Duration as ConfigDuration, GlobalMonitoringOptions, KittypawsConfig, PluginConfig,
+    check_plain_name, load_manifest, Duration as ConfigDuration, KittypawsConfig, PluginConfig,
+    PluginKind, PluginManifest, SteadyStateOptions,
 };
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
 
-const PLUGINS_PATH: &str = "~/.kittypaws/plugins";
+const WASM_MODULE_FILE_NAME: &str = "plugin.wasm";
+/// Status field reporting whether the status probe succeeded
+const PROBE_OK_FIELD: &str = "probe_ok";
+
 pub type CallablePlugin = Box<dyn PluginInterface + Send + Sync + 'static>;
+/// Name of a plugin or probe with the outcome of its preflight check
+type PreflightCheck = (String, Result<(), String>);
 
 #[derive(Debug)]
 pub enum PluginLanguage {
     Python,
     Bash,
+    Executable,
+    Native,
+    Wasm,
+}
+
+impl From<PluginKind> for PluginLanguage {
+    fn from(value: PluginKind) -> Self {
+        match value {
+            PluginKind::Python => PluginLanguage::Python,
+            PluginKind::Bash => PluginLanguage::Bash,
+            PluginKind::Executable => PluginLanguage::Executable,
+            PluginKind::Native => PluginLanguage::Native,
+            PluginKind::Wasm => PluginLanguage::Wasm,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum PluginStatusValue {
+    Bool(bool),
     Int(i64),
+    UInt(u64),
     Float(f64),
     String(String),
+    Duration(Duration),
+    Timestamp(DateTime<Utc>),
+}
+
+impl PluginStatusValue {
+    /// Value of a `key=value` status line, typed by the way it looks
+    pub fn parse(value: &str) -> Self {
+        if let Ok(value) = value.parse::<bool>() {
+            Self::Bool(value)
+        } else if let Ok(value) = value.parse::<i64>() {
+            Self::Int(value)
+        } else if let Ok(value) = value.parse::<u64>() {
+            Self::UInt(value)
+        } else if let Ok(value) = value.parse::<f64>() {
+            Self::Float(value)
+        } else {
+            Self::from_text(value)
+        }
+    }
+
+    /// RFC 3339 timestamp, ISO 8601 duration or just a string
+    pub fn from_text(value: &str) -> Self {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+            return Self::Timestamp(timestamp.with_timezone(&Utc));
+        }
+        if value.starts_with('P') {
+            if let Ok(duration) = iso8601::duration(value) {
+                return Self::Duration(duration.into());
+            }
+        }
+        Self::String(value.to_string())
+    }
 }
 
 impl From<&PluginStatusValue> for paws_monitoring::StatusValue {
     fn from(value: &PluginStatusValue) -> Self {
         match value {
+            PluginStatusValue::Bool(val) => Self::Bool(val.to_owned()),
             PluginStatusValue::Int(val) => Self::Int(val.to_owned()),
+            PluginStatusValue::UInt(val) => Self::UInt(val.to_owned()),
             PluginStatusValue::Float(val) => Self::Float(val.to_owned()),
             PluginStatusValue::String(val) => Self::String(val.to_owned()),
+            PluginStatusValue::Duration(val) => Self::Duration(val.to_owned()),
+            PluginStatusValue::Timestamp(val) => Self::Timestamp((*val).into()),
         }
     }
 }
@@ -50,6 +132,30 @@ pub trait PluginInterface {
         &self,
         config: &HashMap<String, String>,
     ) -> Result<HashMap<String, PluginStatusValue>, String>;
+    fn rollback(&self, config: &HashMap<String, String>) -> Result<(), String>;
+    /// Ends a fault held for the configured `hold` after the run
+    fn revert(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.rollback(config)
+    }
+    /// Preflight check before anything is started, e.g. that the target exists
+    fn check(&self, _config: &HashMap<String, String>) -> Result<(), String> {
+        Ok(())
+    }
+    /// Called once before the first run
+    fn setup(&self, _config: &HashMap<String, String>) -> Result<(), String> {
+        Ok(())
+    }
+    /// Called once after the last run
+    fn teardown(&self, _config: &HashMap<String, String>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecAction {
+    Run,
+    Status,
+    Rollback,
 }
 
 #[derive(Debug)]
@@ -71,13 +177,93 @@ impl From<paws_config::StartupOptions> for StartupMode {
     }
 }
 
-fn call_plugin(name: &str, plugin: &CallablePlugin, config: &HashMap<String, String>) {
+fn call_plugin(
+    name: &str,
+    plugin: &CallablePlugin,
+    config: &HashMap<String, String>,
+    hold: Option<Duration>,
+    context: &PluginContext,
+) {
     println!("{}", style_line(name.to_string(), "Running...".to_string()));
-    if let Err(err) = plugin.run(config) {
-        panic!("Error while running plugin {}: {}", name, err);
+    let started = Utc::now();
+    let result = plugin.run(config);
+    if let Some(hold) = hold {
+        hold_fault(name, plugin, config, hold, context, started, result.is_ok());
+    }
+    if let Err(err) = result {
+        if shutdown::is_requested() {
+            println!(
+                "{}",
+                style_line(name.to_string(), format!("Interrupted: {}", err))
+            );
+            return;
+        }
+        println!(
+            "{}",
+            style_line(name.to_string(), format!("Run failed: {}", err))
+        );
+    }
+}
+
+/// Keeps the fault injected by the run for the hold time, then reverts it.
+/// Failed runs are reverted right away. Interrupting the run only cuts the hold short.
+/// The interval the fault was active is reported as a `fault` event.
+fn hold_fault(
+    name: &str,
+    plugin: &CallablePlugin,
+    config: &HashMap<String, String>,
+    duration: Duration,
+    context: &PluginContext,
+    started: DateTime<Utc>,
+    injected: bool,
+) {
+    if injected {
+        println!(
+            "{}",
+            style_line(
+                name.to_string(),
+                format!("Holding for {:.1}s...", duration.as_secs_f64())
+            )
+        );
+        wait_duration(duration);
     }
+
+    println!(
+        "{}",
+        style_line(name.to_string(), "Reverting...".to_string())
+    );
+    let reverted = plugin.revert(config);
+    if let Err(err) = &reverted {
+        println!(
+            "{}",
+            style_line(
+                name.to_string(),
+                format!("! WARNING: Revert failed: {}", err)
+            )
+        );
+    }
+
+    let ended = Utc::now();
+    context.emit_event(
+        "fault",
+        HashMap::from([
+            (
+                "started".to_string(),
+                StatusValue::Timestamp(started.into()),
+            ),
+            ("ended".to_string(), StatusValue::Timestamp(ended.into())),
+            (
+                "held".to_string(),
+                StatusValue::Duration((ended - started).to_std().unwrap_or_default()),
+            ),
+            ("reverted".to_string(), StatusValue::Bool(reverted.is_ok())),
+        ]),
+        HashMap::new(),
+    );
 }
 
+/// Status of the plugin with `probe_ok` telling if the status could be fetched.
+/// A failed probe is not fatal, the plugin is probed again next time.
 fn get_status(
     name: &str,
     plugin: &CallablePlugin,
@@ -88,21 +274,31 @@ fn get_status(
         style_line(name.to_string(), "Fetching status...".to_string())
     );
     match plugin.status(config) {
-        Ok(status) => {
+        Ok(mut status) => {
             println!(
                 "{}",
                 style_line(name.to_string(), format!("Status: {:?}", status))
             );
+            status.insert(PROBE_OK_FIELD.to_string(), PluginStatusValue::Bool(true));
             status
         }
-        Err(err) => panic!("Error while running plugin {}: {}", name, err),
+        Err(err) => {
+            println!(
+                "{}",
+                style_line(name.to_string(), format!("Status probe failed: {}", err))
+            );
+            HashMap::from([(PROBE_OK_FIELD.to_string(), PluginStatusValue::Bool(false))])
+        }
     }
 }
 
 fn start_execution_loop(
-    plugin: CallablePlugin,
+    plugin: Arc<CallablePlugin>,
     config: PluginConfig,
     loop_duration: &Option<ConfigDuration>,
+    context: Arc<PluginContext>,
+    recovery: Option<Arc<Recovery>>,
+    breaker: Option<Arc<CircuitBreaker>>,
 ) -> JoinHandle<()> {
     let startup = config.startup.into();
     let mut deadline: Option<DateTime<Utc>> = None;
@@ -120,10 +316,26 @@ fn start_execution_loop(
         };
 
         loop {
+            if shutdown::is_requested() {
+                break;
+            }
+            if let Some(breaker) = &breaker {
+                if !breaker.allows_run(deadline) {
+                    break;
+                }
+            }
+            context.next_iteration();
+            // Picked before the fault is injected, nothing may fail between the run and the revert
+            let hold = config.hold.as_ref().map(hold_duration);
+            if let (Some(recovery), Some(options)) = (&recovery, &config.recovery) {
+                recovery.track(&config.name, options, hold, context.clone());
+            }
             call_plugin(
                 &config.name,
                 &plugin,
                 &config.options.clone().unwrap_or_default(),
+                hold,
+                &context,
             );
             if time_till_next_run(&config.frequency).is_none() {
                 break;
@@ -138,12 +350,11 @@ fn start_execution_loop(
 }
 
 fn start_status_loop(
-    run_id: Uuid,
-    plugin: CallablePlugin,
+    plugin: Arc<CallablePlugin>,
     config: PluginConfig,
     loop_duration: &Option<ConfigDuration>,
-    global_monitoring_config: GlobalMonitoringOptions,
-    mut monitoring_client: Box<dyn MetricSender>,
+    context: Arc<PluginContext>,
+    breaker: Option<Arc<CircuitBreaker>>,
 ) -> Option<JoinHandle<()>> {
     if let Some(plugin_monitoring_config) = config.monitoring.clone() {
         let mut deadline: Option<DateTime<Utc>> = None;
@@ -152,44 +363,38 @@ fn start_status_loop(
             deadline = Some(Utc::now() + loop_duration.as_chrono());
         }
 
-        return Some(thread::spawn(move || loop {
-            let status = get_status(
-                &config.name,
-                &plugin,
-                &config.options.clone().unwrap_or_default(),
-            );
-
-            // unwrap: status loop can't work without monitoring config
-            let mut tags = HashMap::new();
-            tags.insert("name".to_string(), config.name.clone());
-            tags.insert("run_id".to_string(), run_id.to_string());
-
-            if let Some(extra_tags) = global_monitoring_config.extra_tags.clone() {
-                tags.extend(extra_tags);
-            }
-            if let Some(plugin_monitoring_config) = config.monitoring.clone() {
-                if let Some(extra_tags) = plugin_monitoring_config.extra_tags {
-                    tags.extend(extra_tags);
+        return Some(thread::spawn(move || {
+            loop {
+                let status = get_status(
+                    &config.name,
+                    &plugin,
+                    &config.options.clone().unwrap_or_default(),
+                );
+
+                let fields: HashMap<String, paws_monitoring::StatusValue> = status
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.into()))
+                    .collect();
+                context.reporter().metric(context.tags(), fields);
+
+                println!("Status {:?}", status);
+                if let Some(breaker) = &breaker {
+                    breaker.update(&status, &context);
                 }
-            }
-
-            let fields: HashMap<String, paws_monitoring::StatusValue> = status
-                .iter()
-                .map(|(key, value)| (key.to_string(), value.into()))
-                .collect();
-
-            if let Err(err) = monitoring_client.send_metric(tags, fields) {
-                println!("Can't send metric: {}", err);
-            };
-
-            println!("Status {:?}", status);
-            if time_till_next_run(&plugin_monitoring_config.frequency).is_none() {
-                break;
-            }
-            if let Some(deadline) = deadline {
-                if Utc::now() > deadline {
+                if time_till_next_run(&plugin_monitoring_config.frequency).is_none()
+                    || shutdown::is_requested()
+                {
                     break;
                 }
+                if let Some(deadline) = deadline {
+                    if Utc::now() > deadline {
+                        break;
+                    }
+                }
+            }
+            // Nothing updates the breaker anymore, a paused plugin should not wait forever
+            if let Some(breaker) = &breaker {
+                breaker.release();
             }
         }));
     }
@@ -197,94 +402,424 @@ fn start_status_loop(
     None
 }
 
-pub fn start_main_loop(config: KittypawsConfig) {
+/// Id of a configured plugin, unique within the run
+fn instance_id(plugconf: &PluginConfig, taken: &[String]) -> String {
+    let id = plugconf.id.clone().unwrap_or(plugconf.name.clone());
+    let mut unique = id.clone();
+    let mut index = 1;
+    while taken.contains(&unique) {
+        index += 1;
+        unique = format!("{}-{}", id, index);
+    }
+    unique
+}
+
+/// Loads and checks every plugin first, nothing is started unless all of them pass or `force` is set
+pub fn start_main_loop(config: KittypawsConfig, force: bool) -> Result<(), String> {
     let mut handles: Vec<JoinHandle<()>> = Vec::new();
 
     let run_id = Uuid::new_v4();
     println!("RUN ID: {}", run_id);
+    shutdown::install_handler();
+
+    let search_path = PluginSearchPath::new(&config.plugin_dirs.unwrap_or_default());
+    let deadline = config
+        .duration
+        .as_ref()
+        .map(|duration| Utc::now() + duration.as_chrono());
+
+    let mut reporter = Reporter::default();
+    let mut reporter_thread = None;
+    if let Some(monitoring_config) = &config.monitoring {
+        let (started, thread) = Reporter::start(init_monitoring_backend(
+            paws_monitoring::MonitoringBackend::Telegraf,
+            &monitoring_config.dsn,
+        ));
+        reporter = started;
+        reporter_thread = Some(thread);
+    }
+
+    let mut instance_ids: Vec<String> = Vec::new();
+    let mut checks: Vec<PreflightCheck> = Vec::new();
+    let mut prepared: Vec<(Arc<CallablePlugin>, PluginConfig, Arc<PluginContext>)> = Vec::new();
+    let mut running: Vec<(Arc<CallablePlugin>, PluginConfig)> = Vec::new();
 
     for plugconf in config.plugins {
-        // TODO: Stop this uglyness
-        match load_plugin(&plugconf.name) {
-            Ok(plugin) => {
-                if let Some(monitoring_config) = config.monitoring.clone() {
-                    let monitoring_client = init_monitoring_backend(
-                        paws_monitoring::MonitoringBackend::Telegraf,
-                        &monitoring_config.dsn,
-                    );
-                    if let Some(status_thread) = start_status_loop(
-                        run_id,
-                        plugin,
-                        plugconf.clone(),
-                        &config.duration,
-                        monitoring_config,
-                        monitoring_client,
-                    ) {
-                        handles.push(status_thread);
-                    }
-                }
+        if shutdown::is_requested() {
+            break;
+        }
+        search_path.warn_if_shadowed(&plugconf.name);
+
+        let instance_id = instance_id(&plugconf, &instance_ids);
+        instance_ids.push(instance_id.clone());
+        let mut extra_tags = config
+            .monitoring
+            .as_ref()
+            .and_then(|monitoring| monitoring.extra_tags.clone())
+            .unwrap_or_default();
+        if let Some(plugin_tags) = plugconf
+            .monitoring
+            .as_ref()
+            .and_then(|monitoring| monitoring.extra_tags.clone())
+        {
+            extra_tags.extend(plugin_tags);
+        }
+        let context = Arc::new(PluginContext::new(
+            run_id,
+            &plugconf.name,
+            &instance_id,
+            deadline,
+            extra_tags,
+            reporter.clone(),
+        ));
+
+        let options = plugconf.options.clone().unwrap_or_default();
+        let loaded = find_plugin(&plugconf.name, &search_path)
+            .and_then(|(_, manifest)| manifest.check_options(&options))
+            .and_then(|_| check_recovery_probe(&plugconf, &config.steady_state))
+            .and_then(|_| load_plugin(&plugconf, &search_path, &context));
+        let check = match &loaded {
+            Ok(plugin) => plugin.check(&options),
+            Err(err) => Err(err.clone()),
+        };
+        checks.push((instance_id, check));
+        if let Ok(plugin) = loaded {
+            prepared.push((Arc::new(plugin), plugconf, context));
+        }
+    }
+
+    let steady_state = config.steady_state.as_ref().map(|options| {
+        let context = Arc::new(PluginContext::new(
+            run_id,
+            "steady_state",
+            "steady-state",
+            deadline,
+            config
+                .monitoring
+                .as_ref()
+                .and_then(|monitoring| monitoring.extra_tags.clone())
+                .unwrap_or_default(),
+            reporter.clone(),
+        ));
+        let (steady_state, probe_checks) = SteadyState::load(options, &search_path, &context);
+        checks.extend(probe_checks);
+        Arc::new(steady_state)
+    });
+
+    print_preflight(&checks);
+    let mut refused = None;
+    if checks.iter().any(|(_, check)| check.is_err()) {
+        if force {
+            println!("! WARNING: Preflight check failed, running anyway");
+        } else {
+            refused =
+                Some("Preflight check failed, nothing was started. Use --force to run anyway");
+        }
+    }
+    if refused.is_none() {
+        if let Some(steady_state) = &steady_state {
+            if !steady_state.verify("before") {
+                refused = Some("Steady state is not met, nothing was started");
             }
-            Err(err) => println!("! WARNING: {}", err),
         }
-        match load_plugin(&plugconf.name) {
-            Ok(plugin) => {
-                let exec_thread = start_execution_loop(plugin, plugconf, &config.duration);
+    }
+    if let Some(reason) = refused {
+        if let Some(steady_state) = steady_state {
+            steady_state.teardown();
+        }
+        // Contexts hold on to the reporter
+        drop(prepared);
+        stop_reporter(reporter, reporter_thread);
+        return Err(reason.to_string());
+    }
+    let recovery = steady_state
+        .as_ref()
+        .map(|steady_state| Arc::new(Recovery::new(steady_state.clone())));
 
-                handles.push(exec_thread);
+    for (plugin, plugconf, context) in prepared {
+        if shutdown::is_requested() {
+            break;
+        }
+        if let Err(err) = plugin.setup(&plugconf.options.clone().unwrap_or_default()) {
+            println!(
+                "! WARNING: Setup of plugin {} failed: {}",
+                plugconf.name, err
+            );
+            continue;
+        }
+        running.push((plugin.clone(), plugconf.clone()));
+
+        // Thresholds need the status even when it's not reported anywhere
+        let breaker = plugconf
+            .monitoring
+            .as_ref()
+            .and_then(CircuitBreaker::new)
+            .map(Arc::new);
+        if reporter.is_enabled() || breaker.is_some() {
+            if let Some(status_thread) = start_status_loop(
+                plugin.clone(),
+                plugconf.clone(),
+                &config.duration,
+                context.clone(),
+                breaker.clone(),
+            ) {
+                handles.push(status_thread);
             }
-            Err(err) => println!("! WARNING: {}", err),
         }
+        let exec_thread = start_execution_loop(
+            plugin,
+            plugconf,
+            &config.duration,
+            context,
+            recovery.clone(),
+            breaker,
+        );
+        handles.push(exec_thread);
     }
 
+    let (stop_monitor, monitor_stopped) = mpsc::channel::<()>();
+    let monitor = steady_state
+        .as_ref()
+        .map(|steady_state| steady_state.monitor(monitor_stopped));
+
     for handle in handles {
         if let Err(e) = handle.join() {
             println!("Error: {:?}", e);
         }
     }
 
+    drop(stop_monitor);
+    let violated = monitor.is_some_and(|monitor| monitor.join().unwrap_or_default());
+    if violated {
+        for (plugin, plugconf) in &running {
+            println!(
+                "{}",
+                style_line(plugconf.name.clone(), "Rolling back...".to_string())
+            );
+            if let Err(err) = plugin.rollback(&plugconf.options.clone().unwrap_or_default()) {
+                println!(
+                    "! WARNING: Rollback of plugin {} failed: {}",
+                    plugconf.name, err
+                );
+            }
+        }
+    }
+
+    for (plugin, plugconf) in running {
+        if let Err(err) = plugin.teardown(&plugconf.options.unwrap_or_default()) {
+            println!(
+                "! WARNING: Teardown of plugin {} failed: {}",
+                plugconf.name, err
+            );
+        }
+    }
+
+    // Done before the reporter stops, recovery holds on to it through the probes
+    let incidents = recovery.map(|recovery| recovery.finish());
+    let recovered = steady_state.map(|steady_state| {
+        let recovered = steady_state.verify("after");
+        steady_state.teardown();
+        recovered
+    });
+
+    stop_reporter(reporter, reporter_thread);
+
     println!("---");
+    if let Some(incidents) = &incidents {
+        incidents.print_summary();
+    }
     println!("RUN ID: {}", run_id);
+    if violated {
+        return Err("Steady state was violated, the run was stopped and rolled back".to_string());
+    }
+    if recovered == Some(false) {
+        return Err("System has not recovered its steady state after the run".to_string());
+    }
+    Ok(())
 }
 
-fn unwrap_home_path(path: &str) -> PathBuf {
-    if path.starts_with('~') {
-        match std::env::var("HOME") {
-            Ok(home) => PathBuf::from(&path.replace('~', &home)),
-            Err(_) => {
-                println!("Could not find home directory");
-                PathBuf::from(path)
-            }
-        }
-    } else {
-        PathBuf::from(path)
+/// Reporter thread sends what's left once every plugin is gone
+fn stop_reporter(reporter: Reporter, thread: Option<JoinHandle<()>>) {
+    drop(reporter);
+    if let Some(thread) = thread {
+        let _ = thread.join();
     }
 }
 
-fn get_files_list(path: &PathBuf) -> Vec<String> {
-    dbg!(path);
-    return fs::read_dir(path)
-        .unwrap()
-        .map(|x| x.unwrap().file_name().to_str().unwrap().to_string())
-        .collect::<Vec<String>>();
+/// Recovery is timed with a steady-state probe, it has to be configured
+fn check_recovery_probe(
+    plugconf: &PluginConfig,
+    steady_state: &Option<SteadyStateOptions>,
+) -> Result<(), String> {
+    let Some(recovery) = &plugconf.recovery else {
+        return Ok(());
+    };
+    let known = steady_state.as_ref().is_some_and(|steady_state| {
+        steady_state
+            .probes
+            .iter()
+            .any(|probe| probe.name == recovery.probe)
+    });
+    if !known {
+        return Err(format!(
+            "No steady-state probe named {} to time recovery with",
+            recovery.probe
+        ));
+    }
+    Ok(())
 }
 
-fn get_path_to_plugin(name: &str) -> PathBuf {
-    let path_to_plugins = unwrap_home_path(PLUGINS_PATH);
-    path_to_plugins.join(name)
+fn print_preflight(checks: &[PreflightCheck]) {
+    let width = checks
+        .iter()
+        .map(|(instance_id, _)| instance_id.len())
+        .max()
+        .unwrap_or_default();
+    println!("Preflight check:");
+    for (instance_id, check) in checks {
+        match check {
+            Ok(()) => println!("  {:width$}  ok", instance_id),
+            Err(err) => println!("  {:width$}  FAILED: {}", instance_id, err),
+        }
+    }
 }
 
-fn detect_language(name: &str) -> PluginLanguage {
-    let path_to_plugin = get_path_to_plugin(name);
-    if get_files_list(&path_to_plugin).contains(&"run.sh".to_string()) {
+fn detect_language(path_to_plugin: &Path, manifest: &PluginManifest) -> PluginLanguage {
+    if let Some(kind) = manifest.kind {
+        return kind.into();
+    }
+    if path_to_plugin.join("run.sh").exists() {
         return PluginLanguage::Bash;
     }
+    if path_to_plugin.join(library_file_name()).exists() {
+        return PluginLanguage::Native;
+    }
+    if path_to_plugin.join(WASM_MODULE_FILE_NAME).exists() {
+        return PluginLanguage::Wasm;
+    }
+    if !path_to_plugin.join("main.py").exists() && manifest.run.entrypoint.is_some() {
+        return PluginLanguage::Executable;
+    }
 
     PluginLanguage::Python
 }
 
-fn load_plugin(name: &str) -> Result<CallablePlugin, String> {
-    match detect_language(name) {
-        PluginLanguage::Python => load_py_plugin(name),
-        PluginLanguage::Bash => load_sh_plugin(name),
+pub fn exec_plugin(
+    name: &str,
+    config: &HashMap<String, String>,
+    action: ExecAction,
+) -> Result<(), String> {
+    let search_path = PluginSearchPath::new(&[]);
+    search_path.warn_if_shadowed(name);
+    let plugconf = PluginConfig {
+        name: name.to_string(),
+        options: Some(config.clone()),
+        ..Default::default()
+    };
+    let context = Arc::new(PluginContext::standalone(name));
+    let plugin = load_plugin(&plugconf, &search_path, &context)?;
+    plugin.setup(config)?;
+
+    let result = match action {
+        ExecAction::Run => {
+            println!("{}", style_line(name.to_string(), "Running...".to_string()));
+            context.next_iteration();
+            plugin.run(config)
+        }
+        ExecAction::Status => {
+            println!(
+                "{}",
+                style_line(name.to_string(), "Fetching status...".to_string())
+            );
+            plugin.status(config).map(|status| {
+                for (key, value) in status {
+                    println!(
+                        "{}",
+                        style_line(name.to_string(), format!("{}={:?}", key, value))
+                    );
+                }
+            })
+        }
+        ExecAction::Rollback => {
+            println!(
+                "{}",
+                style_line(name.to_string(), "Rolling back...".to_string())
+            );
+            plugin.rollback(config)
+        }
+    };
+    let teardown = plugin.teardown(config);
+
+    result.and(teardown)
+}
+
+/// Plugin directory and manifest, default one if the plugin has none
+fn find_plugin(
+    name: &str,
+    search_path: &PluginSearchPath,
+) -> Result<(PathBuf, PluginManifest), String> {
+    let Some(path_to_plugin) = search_path.find(name) else {
+        return Err(format!(
+            "Plugin {} is not found in {:?}",
+            name,
+            search_path.dirs()
+        ));
+    };
+
+    let manifest = load_manifest(&path_to_plugin)?.unwrap_or_default();
+    Ok((path_to_plugin, manifest))
+}
+
+fn load_plugin(
+    plugconf: &PluginConfig,
+    search_path: &PluginSearchPath,
+    context: &Arc<PluginContext>,
+) -> Result<CallablePlugin, String> {
+    let name = plugconf.name.as_str();
+    // The instance id falls back to the name and names the state dir
+    check_plain_name(&context.instance_id).map_err(|err| format!("Plugin instance {}", err))?;
+    let (path_to_plugin, manifest) = find_plugin(name, search_path)?;
+    context.create_state_dir()?;
+
+    match detect_language(&path_to_plugin, &manifest) {
+        PluginLanguage::Python => {
+            let timeout = plugconf.timeout.as_ref().map(|timeout| timeout.as_std());
+            match &plugconf.python {
+                Some(python) => load_py_process_plugin(
+                    name,
+                    &path_to_plugin,
+                    &manifest,
+                    python,
+                    timeout,
+                    context,
+                ),
+                None => match ensure_venv(name, &path_to_plugin)? {
+                    Some(python) => {
+                        context.log(&format!(
+                            "Has dependencies, running out of process with {}",
+                            python.display()
+                        ));
+                        load_py_process_plugin(
+                            name,
+                            &path_to_plugin,
+                            &manifest,
+                            &python,
+                            timeout,
+                            context,
+                        )
+                    }
+                    None => load_py_plugin(name, &path_to_plugin, &manifest, context),
+                },
+            }
+        }
+        PluginLanguage::Bash => load_sh_plugin(name, &path_to_plugin, &manifest, context),
+        PluginLanguage::Executable => load_exec_plugin(name, &path_to_plugin, &manifest, context),
+        PluginLanguage::Native => load_native_plugin(name, &path_to_plugin, &manifest),
+        #[cfg(feature = "wasm")]
+        PluginLanguage::Wasm => {
+            load_wasm_plugin(name, &path_to_plugin, &manifest, plugconf.sandbox.as_ref())
+        }
+        #[cfg(not(feature = "wasm"))]
+        PluginLanguage::Wasm => Err("paws is built without WebAssembly support".to_string()),
     }
 }
@@ -1,100 +1,231 @@
-use crate::plug::{unwrap_home_path, CallablePlugin, PluginInterface, PLUGINS_PATH};
-use std::collections::HashMap;
-use std::io::BufRead;
+use crate::plug::command_plugin::{load as load_command_plugin, CommandEntrypoints};
+use crate::plug::{CallablePlugin, PluginContext};
+use paws_config::{EntrypointOptions, PluginManifest};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::Arc;
 
-use super::PluginStatusValue;
+const INTERPRETER: &str = "bash -C";
 
-struct BashCommand {
-    executable: PathBuf,
-    status_checker: Option<PathBuf>,
+/// Script from the manifest or the conventional file if it exists
+///
+/// A manifest block without an entrypoint still uses the conventional file.
+fn script(
+    path_to_plugin: &Path,
+    declared: Option<&EntrypointOptions>,
+    file_name: &str,
+) -> Option<EntrypointOptions> {
+    if let Some(declared) = declared {
+        return Some(EntrypointOptions {
+            entrypoint: Some(
+                declared
+                    .entrypoint
+                    .clone()
+                    .unwrap_or(PathBuf::from(file_name)),
+            ),
+            ..declared.clone()
+        });
+    }
+
+    path_to_plugin
+        .join(file_name)
+        .exists()
+        .then(|| EntrypointOptions {
+            entrypoint: Some(PathBuf::from(file_name)),
+            ..Default::default()
+        })
 }
 
-impl PluginInterface for BashCommand {
-    fn run(&self, config: &HashMap<String, String>) -> Result<(), String> {
-        // get a string of args and values from hashmap
-        let output = Command::new("bash")
-            .envs(config)
-            .arg("-C")
-            .arg(self.executable.to_str().unwrap())
-            // .args(&args)
-            .output()
-            .expect("failed to execute process {}");
-
-        #[cfg(debug_assertions)]
-        println!("Run stdout: {}", String::from_utf8(output.stdout).unwrap());
-        #[cfg(debug_assertions)]
-        println!("Run stderr: {}", String::from_utf8(output.stderr).unwrap());
-
-        Ok(())
-    }
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    manifest: &PluginManifest,
+    context: &Arc<PluginContext>,
+) -> Result<CallablePlugin, String> {
+    let run = EntrypointOptions {
+        entrypoint: Some(
+            manifest
+                .run
+                .entrypoint
+                .clone()
+                .unwrap_or(PathBuf::from("run.sh")),
+        ),
+        interpreter: Some(
+            manifest
+                .run
+                .interpreter
+                .clone()
+                .unwrap_or(INTERPRETER.to_string()),
+        ),
+        args: manifest.run.args.clone(),
+        ..Default::default()
+    };
+    let status = script(path_to_plugin, manifest.status.as_ref(), "status.sh")
+        .map(|status| status.inherit(&run));
+    let rollback = script(path_to_plugin, manifest.rollback.as_ref(), "rollback.sh")
+        .map(|rollback| rollback.inherit(&run));
+    let revert = script(path_to_plugin, manifest.revert.as_ref(), "revert.sh")
+        .map(|revert| revert.inherit(&run));
+    let check = script(path_to_plugin, manifest.check.as_ref(), "check.sh")
+        .map(|check| check.inherit(&run));
+    let setup = script(path_to_plugin, manifest.setup.as_ref(), "setup.sh")
+        .map(|setup| setup.inherit(&run));
+    let teardown = script(path_to_plugin, manifest.teardown.as_ref(), "teardown.sh")
+        .map(|teardown| teardown.inherit(&run));
 
-    fn status(
-        &self,
-        config: &HashMap<String, String>,
-    ) -> Result<HashMap<String, PluginStatusValue>, String> {
-        // get a string of args and values from hashmap
-        if let Some(command) = &self.status_checker {
-            let output = Command::new("bash")
-                .envs(config)
-                .arg("-C")
-                .arg(command.to_str().unwrap())
-                // .args(&args)
-                .output()
-                .expect("failed to execute process {}");
-
-            #[cfg(debug_assertions)]
-            println!(
-                "Status stderr: {}",
-                String::from_utf8(output.stderr).unwrap()
-            );
-
-            let mut status = HashMap::new();
-            for key_value in output.stdout.lines() {
-                if let Ok(key_value) = key_value {
-                    if let Some((key, value)) = key_value.split_once("=") {
-                        let mut parsed_value = PluginStatusValue::String(value.to_string());
-                        if let Ok(value) = value.parse::<i64>() {
-                            parsed_value = PluginStatusValue::Int(value);
-                        } else if let Ok(value) = value.parse::<f64>() {
-                            parsed_value = PluginStatusValue::Float(value);
-                        }
-                        status.insert(key.to_string(), parsed_value);
-                    }
-                }
-            }
-            return Ok(status);
-        }
-
-        unreachable!()
-    }
+    load_command_plugin(
+        name,
+        path_to_plugin,
+        CommandEntrypoints {
+            run: &run,
+            status: status.as_ref(),
+            rollback: rollback.as_ref(),
+            revert: revert.as_ref(),
+            check: check.as_ref(),
+            setup: setup.as_ref(),
+            teardown: teardown.as_ref(),
+        },
+        context,
+    )
 }
 
-pub fn load(name: &str) -> Result<CallablePlugin, String> {
-    let plugins_path = unwrap_home_path(PLUGINS_PATH);
-    let plugins_dirname = plugins_path
-        .to_str()
-        .expect("Can't find home directory for the current user");
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use paws_config::{EntrypointOptions, PluginManifest, StatusFormat};
 
-    let entrypoint_path = format!("{}/{}/run.sh", &plugins_dirname, name);
-    let path_to_main = Path::new(&entrypoint_path);
+    use super::load;
+    use crate::plug::{PluginContext, PluginStatusValue};
 
-    let entrypoint_path = format!("{}/{}/status.sh", &plugins_dirname, name);
-    let path_to_status = Path::new(&entrypoint_path);
-    let executable = path_to_main.to_path_buf();
+    #[test]
+    fn test_setup_and_teardown_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("run.sh"), "[ -f \"$chain\" ]\n").unwrap();
+        std::fs::write(dir.path().join("setup.sh"), "touch \"$chain\"\n").unwrap();
+        std::fs::write(dir.path().join("teardown.sh"), "rm \"$chain\"\n").unwrap();
+        let chain = dir.path().join("chain");
+        let config = HashMap::from([("chain".to_string(), chain.display().to_string())]);
+        let context = Arc::new(PluginContext::standalone("chains"));
 
-    let mut status_checker = None;
-    if path_to_status.exists() {
-        status_checker = Some(path_to_status.to_path_buf());
+        let plugin = load("chains", dir.path(), &PluginManifest::default(), &context).unwrap();
+
+        assert!(plugin.run(&config).is_err());
+        plugin.setup(&config).unwrap();
+        assert!(plugin.run(&config).is_ok());
+        plugin.teardown(&config).unwrap();
+        assert!(!chain.exists());
     }
 
-    if !path_to_main.exists() {
-        return Err(format!("No main.py found for plugin: {}", name));
+    #[test]
+    fn test_state_between_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("run.sh"),
+            "[ -f \"$PAWS_STATE/clock\" ] || date > \"$PAWS_STATE/clock\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("rollback.sh"), "rm \"$PAWS_STATE/clock\"\n").unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let mut context = PluginContext::standalone("timeburglar");
+        context.state_dir = state.path().to_path_buf();
+        let context = Arc::new(context);
+
+        let plugin = load(
+            "timeburglar",
+            dir.path(),
+            &PluginManifest::default(),
+            &context,
+        )
+        .unwrap();
+        plugin.run(&HashMap::new()).unwrap();
+        let clock = std::fs::read_to_string(state.path().join("clock")).unwrap();
+        plugin.run(&HashMap::new()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(state.path().join("clock")).unwrap(),
+            clock
+        );
+
+        // Another process of paws picks up the state
+        let plugin = load(
+            "timeburglar",
+            dir.path(),
+            &PluginManifest::default(),
+            &context,
+        )
+        .unwrap();
+        plugin.rollback(&HashMap::new()).unwrap();
+        assert!(!state.path().join("clock").exists());
     }
 
-    Ok(Box::new(BashCommand {
-        executable,
-        status_checker,
-    }))
+    #[test]
+    fn test_check_script() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("run.sh"), "").unwrap();
+        std::fs::write(
+            dir.path().join("check.sh"),
+            "command -v \"$tool\" > /dev/null || { echo \"$tool not found\" >&2; exit 1; }\n",
+        )
+        .unwrap();
+        let context = Arc::new(PluginContext::standalone("dropper"));
+
+        let plugin = load("dropper", dir.path(), &PluginManifest::default(), &context).unwrap();
+
+        assert!(plugin
+            .check(&HashMap::from([("tool".to_string(), "sh".to_string())]))
+            .is_ok());
+        assert!(plugin
+            .check(&HashMap::from([(
+                "tool".to_string(),
+                "no-such-tool".to_string()
+            )]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_revert_script() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("run.sh"), "").unwrap();
+        std::fs::write(dir.path().join("rollback.sh"), "exit 1\n").unwrap();
+        let context = Arc::new(PluginContext::standalone("dropper"));
+
+        let plugin = load("dropper", dir.path(), &PluginManifest::default(), &context).unwrap();
+        // Rollback is used without revert.sh
+        assert!(plugin.revert(&HashMap::new()).is_err());
+
+        std::fs::write(dir.path().join("revert.sh"), "exit 0\n").unwrap();
+        let plugin = load("dropper", dir.path(), &PluginManifest::default(), &context).unwrap();
+        assert!(plugin.revert(&HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn test_status_format_without_entrypoint() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("run.sh"),
+            "touch \"$PAWS_STATE/injected\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("status.sh"), "echo '{\"up\": 3}'\n").unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let mut context = PluginContext::standalone("latency");
+        context.state_dir = state.path().to_path_buf();
+        let context = Arc::new(context);
+        let manifest = PluginManifest {
+            status: Some(EntrypointOptions {
+                format: Some(StatusFormat::Json),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let plugin = load("latency", dir.path(), &manifest, &context).unwrap();
+        let status = plugin.status(&HashMap::new()).unwrap();
+
+        assert!(matches!(
+            status.get("up"),
+            Some(PluginStatusValue::Int(3)) | Some(PluginStatusValue::UInt(3))
+        ));
+        assert!(!state.path().join("injected").exists());
+    }
 }
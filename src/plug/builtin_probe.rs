@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use paws_config::{CommandProbeOptions, HttpProbeOptions, ProbeSource, TcpProbeOptions};
+
+use super::{PluginInterface, PluginStatusValue};
+
+/// Used by probes which can hang when no timeout is configured
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probe done by paws itself, its status has `ok` and `duration` fields
+/// and `error` telling what went wrong when the probe is not ok
+pub struct BuiltinProbe {
+    source: ProbeSource,
+}
+
+impl BuiltinProbe {
+    /// Fails for plugin probes and options the probe can't honour
+    pub fn new(source: &ProbeSource) -> Result<Self, String> {
+        match source {
+            ProbeSource::Plugin { plugin, .. } => {
+                return Err(format!("{} is a plugin, not a built-in probe", plugin))
+            }
+            ProbeSource::Http { http } => {
+                // The HTTP client only takes whole seconds
+                if let Some(timeout) = &http.timeout {
+                    let timeout = timeout.as_std();
+                    if timeout.as_secs() == 0 || timeout.subsec_nanos() != 0 {
+                        return Err(format!(
+                            "HTTP timeout has to be whole seconds, got {}s",
+                            timeout.as_secs_f64()
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(BuiltinProbe {
+            source: source.clone(),
+        })
+    }
+}
+
+fn probe_status(
+    started: Instant,
+    problem: Option<String>,
+    mut fields: HashMap<String, PluginStatusValue>,
+) -> HashMap<String, PluginStatusValue> {
+    fields.insert(
+        "duration".to_string(),
+        PluginStatusValue::Duration(started.elapsed()),
+    );
+    fields.insert("ok".to_string(), PluginStatusValue::Bool(problem.is_none()));
+    if let Some(problem) = problem {
+        fields.insert("error".to_string(), PluginStatusValue::String(problem));
+    }
+    fields
+}
+
+fn http(options: &HttpProbeOptions) -> HashMap<String, PluginStatusValue> {
+    let timeout = options
+        .timeout
+        .as_ref()
+        .map(|timeout| timeout.as_std())
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let started = Instant::now();
+    let response = match minreq::get(&options.url)
+        .with_timeout(timeout.as_secs())
+        .send()
+    {
+        Ok(response) => response,
+        Err(err) => return probe_status(started, Some(err.to_string()), HashMap::new()),
+    };
+    let latency = started.elapsed();
+
+    let mut problems = Vec::new();
+    let status_ok = match options.status {
+        Some(status) => response.status_code == status,
+        None => (200..400).contains(&response.status_code),
+    };
+    if !status_ok {
+        problems.push(format!("unexpected status {}", response.status_code));
+    }
+    if let Some(body) = &options.body {
+        if !response.as_str().is_ok_and(|text| text.contains(body)) {
+            problems.push(format!("body doesn't contain \"{}\"", body));
+        }
+    }
+    if let Some(max_latency) = &options.latency {
+        if latency > max_latency.as_std() {
+            problems.push(format!("took {}ms", latency.as_millis()));
+        }
+    }
+
+    let problem = (!problems.is_empty()).then(|| problems.join(", "));
+    probe_status(
+        started,
+        problem,
+        HashMap::from([(
+            "status_code".to_string(),
+            PluginStatusValue::Int(response.status_code.into()),
+        )]),
+    )
+}
+
+fn tcp(options: &TcpProbeOptions) -> HashMap<String, PluginStatusValue> {
+    let timeout = options
+        .timeout
+        .as_ref()
+        .map(|timeout| timeout.as_std())
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let started = Instant::now();
+    let addresses = match options.address.to_socket_addrs() {
+        Ok(addresses) => addresses.collect::<Vec<_>>(),
+        Err(err) => return probe_status(started, Some(err.to_string()), HashMap::new()),
+    };
+
+    // Every resolved address is tried, e.g. `localhost` may resolve to IPv6 first
+    let mut problem = Some(format!("{} resolves to nothing", options.address));
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(_) => {
+                problem = None;
+                break;
+            }
+            Err(err) => problem = Some(format!("{}: {}", address, err)),
+        }
+    }
+
+    probe_status(started, problem, HashMap::new())
+}
+
+fn dns(host: &str) -> HashMap<String, PluginStatusValue> {
+    let started = Instant::now();
+    let (addresses, problem) = match (host, 0).to_socket_addrs() {
+        Ok(addresses) => match addresses.count() {
+            0 => (0, Some(format!("{} resolves to nothing", host))),
+            count => (count, None),
+        },
+        Err(err) => (0, Some(err.to_string())),
+    };
+
+    probe_status(
+        started,
+        problem,
+        HashMap::from([(
+            "addresses".to_string(),
+            PluginStatusValue::UInt(addresses as u64),
+        )]),
+    )
+}
+
+fn process(name: &str) -> HashMap<String, PluginStatusValue> {
+    let started = Instant::now();
+    let (count, problem) = match Command::new("pgrep").arg("-x").arg(name).output() {
+        Ok(output) => match String::from_utf8_lossy(&output.stdout).lines().count() {
+            0 => (0, Some(format!("no {} process", name))),
+            count => (count, None),
+        },
+        Err(err) => (0, Some(format!("can't run pgrep: {}", err))),
+    };
+
+    probe_status(
+        started,
+        problem,
+        HashMap::from([(
+            "processes".to_string(),
+            PluginStatusValue::UInt(count as u64),
+        )]),
+    )
+}
+
+fn command(options: &CommandProbeOptions) -> HashMap<String, PluginStatusValue> {
+    let started = Instant::now();
+    let output = match Command::new("sh").arg("-c").arg(&options.run).output() {
+        Ok(output) => output,
+        Err(err) => return probe_status(started, Some(err.to_string()), HashMap::new()),
+    };
+
+    // Killed by a signal counts as -1
+    let exit_code = output.status.code().unwrap_or(-1);
+    let problem = (exit_code != options.exit_code.unwrap_or(0)).then(|| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.lines().last() {
+            Some(line) => format!("exit code {}: {}", exit_code, line),
+            None => format!("exit code {}", exit_code),
+        }
+    });
+    probe_status(
+        started,
+        problem,
+        HashMap::from([(
+            "exit_code".to_string(),
+            PluginStatusValue::Int(exit_code.into()),
+        )]),
+    )
+}
+
+impl PluginInterface for BuiltinProbe {
+    fn run(&self, _config: &HashMap<String, String>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn status(
+        &self,
+        _config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        Ok(match &self.source {
+            ProbeSource::Http { http: options } => http(options),
+            ProbeSource::Tcp { tcp: options } => tcp(options),
+            ProbeSource::Dns { dns: host } => dns(host),
+            ProbeSource::Process { process: name } => process(name),
+            ProbeSource::Command { command: options } => command(options),
+            ProbeSource::Plugin { plugin, .. } => {
+                return Err(format!("{} is a plugin, not a built-in probe", plugin))
+            }
+        })
+    }
+
+    fn rollback(&self, _config: &HashMap<String, String>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::process::Command;
+    use std::thread;
+
+    use paws_config::{CommandProbeOptions, HttpProbeOptions, ProbeSource, TcpProbeOptions};
+
+    use super::BuiltinProbe;
+    use crate::plug::{PluginInterface, PluginStatusValue};
+
+    fn status(source: ProbeSource) -> HashMap<String, PluginStatusValue> {
+        BuiltinProbe::new(&source)
+            .unwrap()
+            .status(&HashMap::new())
+            .unwrap()
+    }
+
+    fn is_ok(status: &HashMap<String, PluginStatusValue>) -> bool {
+        matches!(status.get("ok"), Some(PluginStatusValue::Bool(true)))
+    }
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let open = status(ProbeSource::Tcp {
+            tcp: TcpProbeOptions {
+                address: address.clone(),
+                timeout: None,
+            },
+        });
+        assert!(is_ok(&open));
+        assert!(matches!(
+            open.get("duration"),
+            Some(PluginStatusValue::Duration(_))
+        ));
+
+        // Any of the addresses may accept, `localhost` can resolve to IPv6 first
+        let port = listener.local_addr().unwrap().port();
+        assert!(is_ok(&status(ProbeSource::Tcp {
+            tcp: TcpProbeOptions {
+                address: format!("localhost:{}", port),
+                timeout: None,
+            },
+        })));
+
+        drop(listener);
+        let closed = status(ProbeSource::Tcp {
+            tcp: TcpProbeOptions {
+                address,
+                timeout: None,
+            },
+        });
+        assert!(!is_ok(&closed));
+        assert!(matches!(
+            closed.get("error"),
+            Some(PluginStatusValue::String(_))
+        ));
+    }
+
+    #[test]
+    fn test_command_probe() {
+        let probe = |run: &str, exit_code: Option<i32>| {
+            status(ProbeSource::Command {
+                command: CommandProbeOptions {
+                    run: run.to_string(),
+                    exit_code,
+                },
+            })
+        };
+
+        assert!(is_ok(&probe("true", None)));
+        assert!(is_ok(&probe("exit 3", Some(3))));
+        let failed = probe("echo no queue >&2; exit 1", None);
+        assert!(!is_ok(&failed));
+        assert!(matches!(
+            failed.get("exit_code"),
+            Some(PluginStatusValue::Int(1))
+        ));
+        assert!(matches!(
+            failed.get("error"),
+            Some(PluginStatusValue::String(error)) if error == "exit code 1: no queue"
+        ));
+    }
+
+    #[test]
+    fn test_dns_probe() {
+        let resolved = status(ProbeSource::Dns {
+            dns: "localhost".to_string(),
+        });
+        assert!(is_ok(&resolved));
+        assert!(!is_ok(&status(ProbeSource::Dns {
+            dns: "no-such-host.invalid".to_string(),
+        })));
+    }
+
+    #[test]
+    fn test_http_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/health", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for response in [
+                "HTTP/1.1 200 OK\r\nContent-Length: 15\r\n\r\n{\"status\":\"up\"}",
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                // The request ends with an empty line
+                let mut request = BufReader::new(&stream).lines();
+                while !request.next().unwrap().unwrap().is_empty() {}
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let probe = || {
+            status(ProbeSource::Http {
+                http: HttpProbeOptions {
+                    url: url.clone(),
+                    status: None,
+                    body: Some("up".to_string()),
+                    latency: None,
+                    timeout: None,
+                },
+            })
+        };
+
+        let up = probe();
+        assert!(is_ok(&up));
+        assert!(matches!(
+            up.get("status_code"),
+            Some(PluginStatusValue::Int(200))
+        ));
+        let down = probe();
+        assert!(!is_ok(&down));
+        assert!(matches!(
+            down.get("error"),
+            Some(PluginStatusValue::String(error))
+                if error == "unexpected status 503, body doesn't contain \"up\""
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_http_timeout_in_whole_seconds() {
+        let source = |timeout: &str| ProbeSource::Http {
+            http: HttpProbeOptions {
+                url: "http://localhost".to_string(),
+                status: None,
+                body: None,
+                latency: None,
+                timeout: Some(serde_yaml::from_str(timeout).unwrap()),
+            },
+        };
+        assert!(BuiltinProbe::new(&source("PT2S")).is_ok());
+        assert!(BuiltinProbe::new(&source("PT0.5S")).is_err());
+        assert!(BuiltinProbe::new(&source("PT1.5S")).is_err());
+    }
+
+    #[test]
+    fn test_process_probe() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let running = status(ProbeSource::Process {
+            process: "sleep".to_string(),
+        });
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(is_ok(&running));
+        assert!(matches!(
+            running.get("processes"),
+            Some(PluginStatusValue::UInt(count)) if *count >= 1
+        ));
+        let missing = status(ProbeSource::Process {
+            process: "no-such-process".to_string(),
+        });
+        assert!(!is_ok(&missing));
+        assert!(matches!(
+            missing.get("processes"),
+            Some(PluginStatusValue::UInt(0))
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use paws_config::{PluginMonitoringOptions, SustainedCondition};
+use paws_monitoring::StatusValue;
+
+use super::steady_state::violation;
+use super::{PluginContext, PluginStatusValue};
+use crate::intervals::wait_duration;
+use crate::shutdown;
+
+/// How often a paused execution loop checks if it can go on
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    /// Since when the conditions hold without a break
+    stop_since: Option<Instant>,
+    pause_since: Option<Instant>,
+    paused: bool,
+    stopped: bool,
+}
+
+/// Whether the condition has held long enough, `since` tracks when it started holding
+fn sustained(
+    condition: &SustainedCondition,
+    status: &HashMap<String, PluginStatusValue>,
+    since: &mut Option<Instant>,
+    now: Instant,
+) -> bool {
+    if violation(&condition.condition, status).is_some() {
+        *since = None;
+        return false;
+    }
+    let started = *since.get_or_insert(now);
+    now - started >= condition.sustained.unwrap_or_default()
+}
+
+/// Stops or pauses the execution loop of a plugin when its own status says the target is in trouble.
+/// Fed by the status loop.
+pub struct CircuitBreaker {
+    stop_if: Option<SustainedCondition>,
+    pause_if: Option<SustainedCondition>,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    /// `None` when the plugin has no thresholds
+    pub fn new(options: &PluginMonitoringOptions) -> Option<Self> {
+        if options.stop_if.is_none() && options.pause_if.is_none() {
+            return None;
+        }
+        Some(CircuitBreaker {
+            stop_if: options.stop_if.clone(),
+            pause_if: options.pause_if.clone(),
+            state: Mutex::default(),
+        })
+    }
+
+    /// Checks the thresholds against a fresh status, changes are logged and sent as `circuit_breaker` events.
+    /// A stopped plugin stays stopped, the status is not checked anymore.
+    pub fn update(&self, status: &HashMap<String, PluginStatusValue>, context: &PluginContext) {
+        self.update_at(status, context, Instant::now());
+    }
+
+    fn update_at(
+        &self,
+        status: &HashMap<String, PluginStatusValue>,
+        context: &PluginContext,
+        now: Instant,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if state.stopped {
+            return;
+        }
+
+        if let Some(stop_if) = &self.stop_if {
+            if sustained(stop_if, status, &mut state.stop_since, now) {
+                state.stopped = true;
+                report(context, "stop", &format!("{}, stopping", stop_if));
+                return;
+            }
+        }
+        if let Some(pause_if) = &self.pause_if {
+            let paused = sustained(pause_if, status, &mut state.pause_since, now);
+            if paused && !state.paused {
+                report(context, "pause", &format!("{}, pausing", pause_if));
+            }
+            if !paused && state.paused {
+                report(context, "resume", "Resuming");
+            }
+            state.paused = paused;
+        }
+    }
+
+    /// Lets a paused plugin go on once the status isn't fetched anymore
+    pub fn release(&self) {
+        self.state.lock().unwrap().paused = false;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().stopped
+    }
+
+    /// Waits while paused, `false` if the plugin shouldn't run anymore:
+    /// the breaker is tripped, the run is over or interrupted
+    pub fn allows_run(&self, deadline: Option<DateTime<Utc>>) -> bool {
+        while self.is_paused() {
+            if shutdown::is_requested() || deadline.is_some_and(|deadline| Utc::now() > deadline) {
+                return false;
+            }
+            wait_duration(PAUSE_POLL_INTERVAL);
+        }
+        !self.is_stopped()
+    }
+}
+
+fn report(context: &PluginContext, action: &str, message: &str) {
+    context.log(&format!("Circuit breaker: {}", message));
+    context.emit_event(
+        "circuit_breaker",
+        HashMap::from([(
+            "action".to_string(),
+            StatusValue::String(action.to_string()),
+        )]),
+        HashMap::new(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    use paws_config::PluginMonitoringOptions;
+
+    use super::CircuitBreaker;
+    use crate::plug::{PluginContext, PluginStatusValue};
+
+    fn breaker(stop_if: Option<&str>, pause_if: Option<&str>) -> CircuitBreaker {
+        CircuitBreaker::new(&PluginMonitoringOptions {
+            frequency: Default::default(),
+            extra_tags: None,
+            stop_if: stop_if.map(|condition| condition.parse().unwrap()),
+            pause_if: pause_if.map(|condition| condition.parse().unwrap()),
+        })
+        .unwrap()
+    }
+
+    fn status(field: &str, value: i64) -> HashMap<String, PluginStatusValue> {
+        HashMap::from([(field.to_string(), PluginStatusValue::Int(value))])
+    }
+
+    #[test]
+    fn test_stop_for_good() {
+        let context = PluginContext::standalone("test");
+        let breaker = breaker(Some("errors > 10"), None);
+        let now = Instant::now();
+
+        breaker.update_at(&status("errors", 3), &context, now);
+        assert!(breaker.allows_run(None));
+        breaker.update_at(&status("errors", 11), &context, now);
+        breaker.update_at(&status("errors", 0), &context, now);
+        assert!(breaker.is_stopped());
+        assert!(!breaker.allows_run(None));
+    }
+
+    #[test]
+    fn test_pause_when_sustained() {
+        let context = PluginContext::standalone("test");
+        let breaker = breaker(None, Some("queue_depth > 1000 for PT2M"));
+        let now = Instant::now();
+
+        breaker.update_at(&status("queue_depth", 5000), &context, now);
+        assert!(!breaker.is_paused());
+        breaker.update_at(
+            &status("queue_depth", 5000),
+            &context,
+            now + Duration::from_secs(121),
+        );
+        assert!(breaker.is_paused());
+        breaker.update_at(
+            &status("queue_depth", 10),
+            &context,
+            now + Duration::from_secs(130),
+        );
+        assert!(!breaker.is_paused());
+
+        // A dip below the threshold starts the count over
+        breaker.update_at(
+            &status("queue_depth", 5000),
+            &context,
+            now + Duration::from_secs(140),
+        );
+        breaker.update_at(
+            &status("queue_depth", 5000),
+            &context,
+            now + Duration::from_secs(200),
+        );
+        assert!(!breaker.is_paused());
+        assert!(breaker.allows_run(None));
+    }
+}
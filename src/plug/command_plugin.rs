@@ -0,0 +1,616 @@
+use crate::plug::{CallablePlugin, PluginInterface};
+use paws_config::{EntrypointOptions, StatusFormat};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::context::{json_status_value, ReportLine};
+use super::{PluginContext, PluginStatusValue};
+
+/// Env var with the path of the file the command writes its metrics and events to
+const METRICS_ENV: &str = "PAWS_METRICS";
+
+/// How often the metrics file is checked for new lines while the command runs
+const METRICS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Program with arguments called for one of the plugin actions.
+/// Plugin options are passed to it as env vars.
+struct CommandSpec {
+    entrypoint: PathBuf,
+    program: OsString,
+    args: Vec<OsString>,
+}
+
+impl CommandSpec {
+    fn from_options(
+        name: &str,
+        path_to_plugin: &Path,
+        options: &EntrypointOptions,
+    ) -> Result<Self, String> {
+        let Some(entrypoint) = &options.entrypoint else {
+            return Err(format!("No entrypoint declared for plugin: {}", name));
+        };
+        let entrypoint = path_to_plugin.join(entrypoint);
+        if !entrypoint.exists() {
+            return Err(format!(
+                "Entrypoint {} not found for plugin: {}",
+                entrypoint.display(),
+                name
+            ));
+        }
+
+        let mut command: Vec<OsString> = options
+            .interpreter
+            .iter()
+            .flat_map(|interpreter| interpreter.split_whitespace())
+            .map(OsString::from)
+            .collect();
+        command.push(entrypoint.clone().into_os_string());
+        command.extend(options.args.iter().flatten().map(OsString::from));
+
+        let program = command.remove(0);
+        Ok(CommandSpec {
+            entrypoint,
+            program,
+            args: command,
+        })
+    }
+
+    fn command(&self, config: &HashMap<String, String>) -> Command {
+        let mut command = Command::new(&self.program);
+        command.envs(config).args(&self.args);
+        command
+    }
+}
+
+pub(super) fn parse_key_value_status(stdout: &[u8]) -> HashMap<String, PluginStatusValue> {
+    let mut status = HashMap::new();
+    for key_value in stdout.lines().map_while(Result::ok) {
+        if let Some((key, value)) = key_value.split_once('=') {
+            status.insert(key.to_string(), PluginStatusValue::parse(value));
+        }
+    }
+    status
+}
+
+/// Flattens a JSON object printed by a status command, `{"disk": {"used": 1}}` -> `disk.used=1`
+pub(super) fn parse_json_status(
+    stdout: &[u8],
+) -> Result<HashMap<String, PluginStatusValue>, String> {
+    let object: Map<String, Value> = serde_json::from_slice(stdout)
+        .map_err(|err| format!("Status is not a JSON object: {}", err))?;
+
+    fn flatten(
+        prefix: &str,
+        object: Map<String, Value>,
+        status: &mut HashMap<String, PluginStatusValue>,
+    ) {
+        for (key, value) in object {
+            let key = if prefix.is_empty() {
+                key
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                Value::Object(nested) => flatten(&key, nested, status),
+                Value::Null => {}
+                value => {
+                    status.insert(key, json_status_value(value));
+                }
+            }
+        }
+    }
+
+    let mut status = HashMap::new();
+    flatten("", object, &mut status);
+    Ok(status)
+}
+
+/// Status in the declared format, JSON if the output looks like an object and `key=value` otherwise
+pub(super) fn parse_status(
+    stdout: &[u8],
+    format: Option<StatusFormat>,
+) -> Result<HashMap<String, PluginStatusValue>, String> {
+    let looks_like_json = || {
+        String::from_utf8_lossy(stdout)
+            .trim_start()
+            .starts_with('{')
+    };
+    match format {
+        Some(StatusFormat::Json) => parse_json_status(stdout),
+        Some(StatusFormat::KeyValue) => Ok(parse_key_value_status(stdout)),
+        None if looks_like_json() => parse_json_status(stdout),
+        None => Ok(parse_key_value_status(stdout)),
+    }
+}
+
+/// File the command reports to while it runs, one report per line:
+/// `key=value` for a metric or a JSON line like the ones of out-of-process Python plugins.
+/// Reports are sent as soon as their line is complete, the file is removed after the command exits.
+struct MetricsFile {
+    path: PathBuf,
+}
+
+impl MetricsFile {
+    fn create(context: &PluginContext) -> Result<Self, String> {
+        let path = std::env::temp_dir().join(format!(
+            "paws-{}-{}.metrics",
+            context.instance_id,
+            Uuid::new_v4()
+        ));
+        std::fs::File::create(&path)
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+        Ok(MetricsFile { path })
+    }
+
+    /// Follows the file until `done` is set and everything written before that is sent
+    fn follow(
+        &self,
+        context: Arc<PluginContext>,
+        done: Arc<AtomicBool>,
+    ) -> Result<JoinHandle<()>, String> {
+        let file = std::fs::File::open(&self.path)
+            .map_err(|err| format!("Failed to open {}: {}", self.path.display(), err))?;
+
+        Ok(thread::spawn(move || {
+            let mut reader = BufReader::new(file);
+            let mut line = String::new();
+            loop {
+                // Checked before reading, so the last read sees whatever the command wrote
+                let finished = done.load(Ordering::SeqCst);
+                match reader.read_line(&mut line) {
+                    Ok(read) if read > 0 && line.ends_with('\n') => {
+                        send_report(&context, &line);
+                        line.clear();
+                    }
+                    Ok(_) if finished => {
+                        send_report(&context, &line);
+                        return;
+                    }
+                    Ok(_) => thread::park_timeout(METRICS_POLL_INTERVAL),
+                    Err(_) => return,
+                }
+            }
+        }))
+    }
+}
+
+fn send_report(context: &PluginContext, line: &str) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+
+    if line.starts_with('{') {
+        match serde_json::from_str::<ReportLine>(line) {
+            Ok(report) if report.send(context) => {}
+            _ => context.log(&format!("Unknown report: {}", line)),
+        }
+    } else if let Some((key, value)) = line.split_once('=') {
+        context.emit_metric(
+            key,
+            (&PluginStatusValue::parse(value)).into(),
+            HashMap::new(),
+        );
+    } else {
+        context.log(&format!("Unknown report: {}", line));
+    }
+}
+
+impl Drop for MetricsFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Plugin made of external commands: bash scripts or any executables
+struct CommandPlugin {
+    run: CommandSpec,
+    status: Option<CommandSpec>,
+    rollback: Option<CommandSpec>,
+    revert: Option<CommandSpec>,
+    check: Option<CommandSpec>,
+    setup: Option<CommandSpec>,
+    teardown: Option<CommandSpec>,
+    status_format: Option<StatusFormat>,
+    path_to_plugin: PathBuf,
+    context: Arc<PluginContext>,
+}
+
+impl CommandPlugin {
+    /// Env vars describing the run, passed to every command along with the options
+    fn runtime_env(&self) -> Vec<(&'static str, OsString)> {
+        vec![
+            ("PAWS_RUN_ID", self.context.run_id.to_string().into()),
+            ("PAWS_INSTANCE_ID", self.context.instance_id.clone().into()),
+            (
+                "PAWS_ITERATION",
+                self.context.iteration().to_string().into(),
+            ),
+            (
+                "PAWS_PLUGIN_DIR",
+                self.path_to_plugin.clone().into_os_string(),
+            ),
+            (
+                "PAWS_STATE",
+                self.context.state_dir.clone().into_os_string(),
+            ),
+            (
+                "PAWS_DEADLINE",
+                self.context.deadline_rfc3339().unwrap_or_default().into(),
+            ),
+        ]
+    }
+
+    fn output(
+        &self,
+        spec: &CommandSpec,
+        config: &HashMap<String, String>,
+    ) -> Result<Output, String> {
+        let metrics = MetricsFile::create(&self.context)?;
+        let done = Arc::new(AtomicBool::new(false));
+        let follower = metrics.follow(self.context.clone(), done.clone())?;
+        let output = spec
+            .command(config)
+            .envs(self.runtime_env())
+            .env(METRICS_ENV, &metrics.path)
+            .output()
+            .map_err(|err| format!("Failed to execute {}: {}", spec.entrypoint.display(), err));
+        done.store(true, Ordering::SeqCst);
+        follower.thread().unpark();
+        let _ = follower.join();
+        output
+    }
+
+    fn call(
+        &self,
+        label: &str,
+        spec: &CommandSpec,
+        config: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        let output = self.output(spec, config)?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stdout.is_empty() {
+            println!("{} stdout: {}", label, stdout.trim_end());
+        }
+        if !stderr.is_empty() {
+            println!("{} stderr: {}", label, stderr.trim_end());
+        }
+
+        if !output.status.success() {
+            let failure = format!("{} {}", spec.entrypoint.display(), output.status);
+            // Last line of stderr usually says what went wrong
+            return Err(
+                match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+                    Some(reason) => format!("{}: {}", failure, reason.trim()),
+                    None => failure,
+                },
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl PluginInterface for CommandPlugin {
+    fn run(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call("Run", &self.run, config)
+    }
+
+    fn status(
+        &self,
+        config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        let Some(command) = &self.status else {
+            return Err("Plugin has no status entrypoint".to_string());
+        };
+
+        let output = self.output(command, config)?;
+
+        #[cfg(debug_assertions)]
+        println!("Status stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+        if !output.status.success() {
+            // Failed probe, whatever it printed is not a status
+            return Err(format!(
+                "{} {}: {}",
+                command.entrypoint.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ));
+        }
+
+        parse_status(&output.stdout, self.status_format)
+    }
+
+    fn rollback(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.rollback {
+            Some(command) => self.call("Rollback", command, config),
+            None => Err("Plugin has no rollback entrypoint".to_string()),
+        }
+    }
+
+    fn revert(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.revert {
+            Some(command) => self.call("Revert", command, config),
+            None => self.rollback(config),
+        }
+    }
+
+    fn check(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.check {
+            Some(command) => self.call("Check", command, config),
+            None => Ok(()),
+        }
+    }
+
+    fn setup(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.setup {
+            Some(command) => self.call("Setup", command, config),
+            None => Ok(()),
+        }
+    }
+
+    fn teardown(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.teardown {
+            Some(command) => self.call("Teardown", command, config),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Commands for each of the plugin actions, only `run` is required
+pub struct CommandEntrypoints<'a> {
+    pub run: &'a EntrypointOptions,
+    pub status: Option<&'a EntrypointOptions>,
+    pub rollback: Option<&'a EntrypointOptions>,
+    pub revert: Option<&'a EntrypointOptions>,
+    pub check: Option<&'a EntrypointOptions>,
+    pub setup: Option<&'a EntrypointOptions>,
+    pub teardown: Option<&'a EntrypointOptions>,
+}
+
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    entrypoints: CommandEntrypoints,
+    context: &Arc<PluginContext>,
+) -> Result<CallablePlugin, String> {
+    let spec =
+        |options: &EntrypointOptions| CommandSpec::from_options(name, path_to_plugin, options);
+
+    Ok(Box::new(CommandPlugin {
+        run: spec(entrypoints.run)?,
+        status: entrypoints.status.map(spec).transpose()?,
+        rollback: entrypoints.rollback.map(spec).transpose()?,
+        revert: entrypoints.revert.map(spec).transpose()?,
+        check: entrypoints.check.map(spec).transpose()?,
+        setup: entrypoints.setup.map(spec).transpose()?,
+        teardown: entrypoints.teardown.map(spec).transpose()?,
+        status_format: entrypoints.status.and_then(|status| status.format),
+        path_to_plugin: std::path::absolute(path_to_plugin)
+            .unwrap_or_else(|_| path_to_plugin.to_path_buf()),
+        context: context.clone(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use paws_config::{EntrypointOptions, StatusFormat};
+
+    use super::{load, parse_status, CommandEntrypoints};
+    use crate::plug::context::recording_context;
+    use crate::plug::{PluginContext, PluginStatusValue};
+
+    fn entrypoints<'a>(
+        run: &'a EntrypointOptions,
+        status: Option<&'a EntrypointOptions>,
+    ) -> CommandEntrypoints<'a> {
+        CommandEntrypoints {
+            run,
+            status,
+            rollback: None,
+            revert: None,
+            check: None,
+            setup: None,
+            teardown: None,
+        }
+    }
+
+    #[cfg(unix)]
+    fn write_script(path: &Path, contents: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::write(path, contents).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_executable_with_args_and_env() {
+        let dir = tempfile::tempdir().unwrap();
+        write_script(
+            &dir.path().join("tool"),
+            "#!/bin/sh\n[ \"$1\" = status ] && echo \"target=$target\" && echo count=3\nexit 0\n",
+        );
+        let run = EntrypointOptions {
+            entrypoint: Some(PathBuf::from("tool")),
+            args: Some(vec!["inject".to_string()]),
+            ..Default::default()
+        };
+        let status = EntrypointOptions {
+            args: Some(vec!["status".to_string()]),
+            ..Default::default()
+        }
+        .inherit(&run);
+
+        let plugin = load(
+            "tool",
+            dir.path(),
+            entrypoints(&run, Some(&status)),
+            &Arc::new(PluginContext::standalone("tool")),
+        )
+        .unwrap();
+        let config = HashMap::from([("target".to_string(), "db".to_string())]);
+
+        assert!(plugin.run(&config).is_ok());
+        assert!(plugin.rollback(&config).is_err());
+        let status = plugin.status(&config).unwrap();
+        assert!(matches!(&status["target"], PluginStatusValue::String(value) if value == "db"));
+        assert!(matches!(status["count"], PluginStatusValue::Int(3)));
+    }
+
+    #[test]
+    fn test_status_formats() {
+        let status = parse_status(
+            br#"{"healthy": true, "disk": {"used": 0.5, "mount": "/"}, "pid": null}"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(status.len(), 3);
+        assert!(matches!(status["healthy"], PluginStatusValue::Bool(true)));
+        assert!(matches!(status["disk.used"], PluginStatusValue::Float(value) if value == 0.5));
+        assert!(matches!(&status["disk.mount"], PluginStatusValue::String(value) if value == "/"));
+
+        let status = parse_status(
+            b"healthy=true\nretries=2\nsent=18446744073709551615\nuptime=PT1M30S\nrestarted=2024-01-01T00:00:00Z\nmode=PASSIVE\n",
+            None,
+        )
+        .unwrap();
+        assert!(matches!(status["healthy"], PluginStatusValue::Bool(true)));
+        assert!(matches!(status["retries"], PluginStatusValue::Int(2)));
+        assert!(matches!(status["sent"], PluginStatusValue::UInt(u64::MAX)));
+        assert!(
+            matches!(status["uptime"], PluginStatusValue::Duration(value) if value.as_secs() == 90)
+        );
+        assert!(
+            matches!(status["restarted"], PluginStatusValue::Timestamp(value) if value.timestamp() == 1704067200)
+        );
+        assert!(matches!(&status["mode"], PluginStatusValue::String(value) if value == "PASSIVE"));
+
+        assert!(parse_status(b"healthy=true", Some(StatusFormat::Json)).is_err());
+        let status = parse_status(b"{json=yes", Some(StatusFormat::KeyValue)).unwrap();
+        assert!(matches!(&status["{json"], PluginStatusValue::String(value) if value == "yes"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_status_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        write_script(
+            &dir.path().join("status.sh"),
+            "#!/bin/sh\necho healthy=false\necho unreachable >&2\nexit 3\n",
+        );
+        let status = EntrypointOptions {
+            entrypoint: Some(PathBuf::from("status.sh")),
+            ..Default::default()
+        };
+        let context = Arc::new(PluginContext::standalone("tool"));
+
+        let plugin = load(
+            "tool",
+            dir.path(),
+            entrypoints(&status, Some(&status)),
+            &context,
+        )
+        .unwrap();
+
+        let err = plugin.status(&HashMap::new()).unwrap_err();
+        assert!(err.contains("unreachable"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_entrypoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = EntrypointOptions {
+            entrypoint: Some(PathBuf::from("nope")),
+            ..Default::default()
+        };
+
+        let context = Arc::new(PluginContext::standalone("tool"));
+
+        assert!(load("tool", dir.path(), entrypoints(&run, None), &context).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runtime_env_and_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        write_script(
+            &dir.path().join("tool"),
+            concat!(
+                "#!/bin/sh\n",
+                "echo \"instance=$PAWS_INSTANCE_ID\" >> \"$PAWS_METRICS\"\n",
+                "echo \"iteration=$PAWS_ITERATION\" >> \"$PAWS_METRICS\"\n",
+                "[ -x \"$PAWS_PLUGIN_DIR/tool\" ] && [ -n \"$PAWS_RUN_ID\" ] || exit 1\n",
+                "echo '{\"event\": {\"name\": \"restarted\", \"fields\": {\"container\": \"db\"}}}' >> \"$PAWS_METRICS\"\n",
+            ),
+        );
+        let run = EntrypointOptions {
+            entrypoint: Some(PathBuf::from("tool")),
+            ..Default::default()
+        };
+        let (context, wait_for) = recording_context("tool-1");
+        context.next_iteration();
+
+        let plugin = load("tool", dir.path(), entrypoints(&run, None), &context).unwrap();
+
+        assert!(plugin.run(&HashMap::new()).is_ok());
+        let mut reports: Vec<String> = wait_for(3).into_iter().map(|(fields, _)| fields).collect();
+        reports.sort();
+        assert_eq!(
+            reports,
+            vec![
+                "container=String(\"db\"),event=String(\"restarted\")",
+                "instance=String(\"tool-1\")",
+                "iteration=Int(1)",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_metrics_sent_while_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let released = dir.path().join("released");
+        write_script(
+            &dir.path().join("tool"),
+            &format!(
+                concat!(
+                    "#!/bin/sh\n",
+                    "echo started=1 >> \"$PAWS_METRICS\"\n",
+                    "for _ in $(seq 100); do [ -f {} ] && exit 0; sleep 0.05; done\n",
+                    "exit 1\n",
+                ),
+                released.display()
+            ),
+        );
+        let run = EntrypointOptions {
+            entrypoint: Some(PathBuf::from("tool")),
+            ..Default::default()
+        };
+        let (context, wait_for) = recording_context("tool-1");
+        let plugin = load("tool", dir.path(), entrypoints(&run, None), &context).unwrap();
+
+        let running = std::thread::spawn(move || plugin.run(&HashMap::new()));
+        // The script waits for this report to be sent before it exits
+        assert_eq!(wait_for(1).len(), 1);
+        std::fs::write(&released, "").unwrap();
+
+        assert!(running.join().unwrap().is_ok());
+    }
+}
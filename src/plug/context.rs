@@ -0,0 +1,283 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use paws_install::get_state_path;
+use paws_monitoring::{Reporter, StatusValue};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+use super::PluginStatusValue;
+use crate::stdout_styling::style_line;
+
+/// Runtime information about a configured plugin instance, shared with the plugin.
+/// Lives for the whole run, `iteration` is advanced by the execution loop.
+pub struct PluginContext {
+    pub run_id: Uuid,
+    pub instance_id: String,
+    pub deadline: Option<DateTime<Utc>>,
+    /// Directory with the instance state, a file per key. Survives paws restarts.
+    pub state_dir: PathBuf,
+    iteration: AtomicU64,
+    tags: HashMap<String, String>,
+    reporter: Reporter,
+}
+
+impl PluginContext {
+    pub fn new(
+        run_id: Uuid,
+        name: &str,
+        instance_id: &str,
+        deadline: Option<DateTime<Utc>>,
+        extra_tags: HashMap<String, String>,
+        reporter: Reporter,
+    ) -> Self {
+        let mut tags = extra_tags;
+        tags.insert("name".to_string(), name.to_string());
+        tags.insert("instance".to_string(), instance_id.to_string());
+        tags.insert("run_id".to_string(), run_id.to_string());
+
+        PluginContext {
+            run_id,
+            instance_id: instance_id.to_string(),
+            deadline,
+            state_dir: get_state_path().join(instance_id),
+            iteration: AtomicU64::new(0),
+            tags,
+            reporter,
+        }
+    }
+
+    /// Context for a single call outside of a run, nothing is reported
+    pub fn standalone(name: &str) -> Self {
+        Self::new(
+            Uuid::new_v4(),
+            name,
+            name,
+            None,
+            HashMap::new(),
+            Reporter::default(),
+        )
+    }
+
+    pub fn create_state_dir(&self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.state_dir).map_err(|err| {
+            format!(
+                "Can't create state directory {}: {}",
+                self.state_dir.display(),
+                err
+            )
+        })
+    }
+
+    /// Number of the current run of the plugin, 0 before the first one
+    pub fn iteration(&self) -> u64 {
+        self.iteration.load(Ordering::SeqCst)
+    }
+
+    pub fn next_iteration(&self) -> u64 {
+        self.iteration.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn deadline_rfc3339(&self) -> Option<String> {
+        self.deadline
+            .map(|deadline| deadline.to_rfc3339_opts(SecondsFormat::Secs, false))
+    }
+
+    /// Tags every metric of the instance is sent with
+    pub fn tags(&self) -> HashMap<String, String> {
+        self.tags.clone()
+    }
+
+    pub fn reporter(&self) -> &Reporter {
+        &self.reporter
+    }
+
+    pub fn log(&self, message: &str) {
+        println!(
+            "{}",
+            style_line(self.instance_id.clone(), message.to_string())
+        );
+    }
+
+    pub fn emit_metric(&self, name: &str, value: StatusValue, tags: HashMap<String, String>) {
+        let mut all_tags = self.tags();
+        all_tags.extend(tags);
+        self.reporter
+            .metric(all_tags, HashMap::from([(name.to_string(), value)]));
+    }
+
+    pub fn emit_event(
+        &self,
+        name: &str,
+        fields: HashMap<String, StatusValue>,
+        tags: HashMap<String, String>,
+    ) {
+        let mut all_tags = self.tags();
+        all_tags.extend(tags);
+        let mut all_fields = fields;
+        all_fields.insert("event".to_string(), StatusValue::String(name.to_string()));
+        self.reporter.event(all_tags, all_fields);
+    }
+}
+
+pub(super) fn json_status_value(value: Value) -> PluginStatusValue {
+    match value {
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(value), _) => PluginStatusValue::Int(value),
+            (None, Some(value)) => PluginStatusValue::UInt(value),
+            _ => PluginStatusValue::Float(number.as_f64().unwrap_or_default()),
+        },
+        Value::Bool(value) => PluginStatusValue::Bool(value),
+        Value::String(value) => PluginStatusValue::from_text(&value),
+        value => PluginStatusValue::String(value.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Report {
+    name: String,
+    value: Option<Value>,
+    fields: Option<HashMap<String, Value>>,
+    tags: Option<HashMap<String, String>>,
+}
+
+/// Log message, metric or event an external plugin reports as a JSON line:
+/// `{"log": "..."}`, `{"metric": {"name": "...", "value": 1, "tags": {}}}`
+/// or `{"event": {"name": "...", "fields": {}, "tags": {}}}`
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ReportLine {
+    log: Option<String>,
+    metric: Option<Report>,
+    event: Option<Report>,
+}
+
+impl ReportLine {
+    /// Passes the report to the context, returns false if there is nothing to report
+    pub fn send(&self, context: &PluginContext) -> bool {
+        if let Some(message) = &self.log {
+            context.log(message);
+        } else if let Some(metric) = &self.metric {
+            let value = json_status_value(metric.value.clone().unwrap_or_default());
+            context.emit_metric(
+                &metric.name,
+                (&value).into(),
+                metric.tags.clone().unwrap_or_default(),
+            );
+        } else if let Some(event) = &self.event {
+            let fields = event
+                .fields
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, (&json_status_value(value)).into()))
+                .collect();
+            context.emit_event(&event.name, fields, event.tags.clone().unwrap_or_default());
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// Fields and tags of a report sent through a context in tests
+#[cfg(test)]
+pub type RecordedReport = (String, HashMap<String, String>);
+
+#[cfg(test)]
+type Recorded = std::sync::Arc<std::sync::Mutex<Vec<RecordedReport>>>;
+
+#[cfg(test)]
+struct Recorder(Recorded);
+
+#[cfg(test)]
+impl paws_monitoring::MetricSender for Recorder {
+    fn send_metric(
+        &mut self,
+        tags: HashMap<String, String>,
+        fields: HashMap<String, StatusValue>,
+    ) -> Result<(), String> {
+        for (key, value) in fields {
+            self.0
+                .lock()
+                .unwrap()
+                .push((format!("{}={:?}", key, value), tags.clone()));
+        }
+        Ok(())
+    }
+
+    fn send_event(
+        &mut self,
+        tags: HashMap<String, String>,
+        fields: HashMap<String, StatusValue>,
+    ) -> Result<(), String> {
+        let mut fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("{}={:?}", key, value))
+            .collect();
+        fields.sort();
+        self.0.lock().unwrap().push((fields.join(","), tags));
+        Ok(())
+    }
+}
+
+/// Context reporting into a list, `wait_for` returns its contents once it has `count` entries
+#[cfg(test)]
+pub fn recording_context(
+    instance_id: &str,
+) -> (
+    std::sync::Arc<PluginContext>,
+    impl Fn(usize) -> Vec<RecordedReport>,
+) {
+    let recorded = Recorded::default();
+    let (reporter, _) = Reporter::start(Box::new(Recorder(recorded.clone())));
+    let context = PluginContext::new(
+        Uuid::new_v4(),
+        "test",
+        instance_id,
+        None,
+        HashMap::new(),
+        reporter,
+    );
+    let wait_for = move |count: usize| {
+        for _ in 0..100 {
+            if recorded.lock().unwrap().len() >= count {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        recorded.lock().unwrap().clone()
+    };
+
+    (std::sync::Arc::new(context), wait_for)
+}
+
+/// Context which reports nowhere
+#[cfg(test)]
+pub fn standalone_context() -> std::sync::Arc<PluginContext> {
+    std::sync::Arc::new(PluginContext::standalone("test"))
+}
+
+/// Python plugin using the `paws` module, run with a `recording_context("reporter-1")`
+#[cfg(test)]
+pub const REPORTING_PLUGIN: &str = "
+import paws
+
+def run(config):
+    paws.logger.info('iteration %d', paws.context.iteration)
+    paws.emit_metric('dropped', 3, tags={'iface': 'eth0'})
+    paws.emit_event('restarted', fields={'container': paws.context.instance_id})
+";
+
+/// What a run of the `REPORTING_PLUGIN` reports
+#[cfg(test)]
+pub fn assert_reported(recorded: &[RecordedReport]) {
+    assert_eq!(recorded[0].0, "dropped=Int(3)");
+    assert_eq!(recorded[0].1["iface"], "eth0");
+    assert_eq!(recorded[0].1["instance"], "reporter-1");
+    assert_eq!(
+        recorded[1].0,
+        "container=String(\"reporter-1\"),event=String(\"restarted\")"
+    );
+}
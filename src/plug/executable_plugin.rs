@@ -0,0 +1,41 @@
+use crate::plug::command_plugin::{load as load_command_plugin, CommandEntrypoints};
+use crate::plug::{CallablePlugin, PluginContext};
+use paws_config::{EntrypointOptions, PluginManifest};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Any executable declared as the entrypoint in the manifest:
+/// compiled binary, shebang script or a file run by the declared interpreter
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    manifest: &PluginManifest,
+    context: &Arc<PluginContext>,
+) -> Result<CallablePlugin, String> {
+    let inherit = |options: &Option<EntrypointOptions>| {
+        options
+            .as_ref()
+            .map(|options| options.inherit(&manifest.run))
+    };
+    let status = inherit(&manifest.status);
+    let rollback = inherit(&manifest.rollback);
+    let revert = inherit(&manifest.revert);
+    let check = inherit(&manifest.check);
+    let setup = inherit(&manifest.setup);
+    let teardown = inherit(&manifest.teardown);
+
+    load_command_plugin(
+        name,
+        path_to_plugin,
+        CommandEntrypoints {
+            run: &manifest.run,
+            status: status.as_ref(),
+            rollback: rollback.as_ref(),
+            revert: revert.as_ref(),
+            check: check.as_ref(),
+            setup: setup.as_ref(),
+            teardown: teardown.as_ref(),
+        },
+        context,
+    )
+}
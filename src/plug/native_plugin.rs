@@ -0,0 +1,273 @@
+use crate::plug::command_plugin::parse_key_value_status;
+use crate::plug::{CallablePlugin, PluginInterface};
+use libloading::Library;
+use paws_config::PluginManifest;
+use paws_plugin::{PawsOption, ABI_VERSION};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::PluginStatusValue;
+
+type VersionFn = unsafe extern "C" fn() -> u32;
+type InitFn = unsafe extern "C" fn() -> *mut c_void;
+type CallFn = unsafe extern "C" fn(*mut c_void, *const PawsOption, usize, *mut *mut c_char) -> i32;
+type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+type FreeFn = unsafe extern "C" fn(*mut c_void);
+
+pub fn library_file_name() -> String {
+    format!("plugin.{}", std::env::consts::DLL_EXTENSION)
+}
+
+/// Shared library implementing the ABI described in `paws_plugin`
+struct NativePlugin {
+    plugin: *mut c_void,
+    lock: Mutex<()>,
+    run: CallFn,
+    status: CallFn,
+    rollback: CallFn,
+    free_string: FreeStringFn,
+    free: FreeFn,
+    // Function pointers above are valid only while the library is loaded
+    _library: Library,
+}
+
+// The ABI does not require plugins to be thread safe,
+// all calls to the instance are serialized with `lock`
+unsafe impl Send for NativePlugin {}
+unsafe impl Sync for NativePlugin {}
+
+impl NativePlugin {
+    fn call(&self, function: CallFn, config: &HashMap<String, String>) -> Result<String, String> {
+        let strings = config
+            .iter()
+            .map(
+                |(key, value)| match (CString::new(key.as_str()), CString::new(value.as_str())) {
+                    (Ok(key), Ok(value)) => Ok((key, value)),
+                    _ => Err(format!(
+                        "Option {} has a NUL byte, it can't be passed to the plugin",
+                        key.escape_debug()
+                    )),
+                },
+            )
+            .collect::<Result<Vec<(CString, CString)>, String>>()?;
+        let options: Vec<PawsOption> = strings
+            .iter()
+            .map(|(key, value)| PawsOption {
+                key: key.as_ptr(),
+                value: value.as_ptr(),
+            })
+            .collect();
+
+        let _guard = self.lock.lock().unwrap();
+        let mut output: *mut c_char = std::ptr::null_mut();
+        let code = unsafe { function(self.plugin, options.as_ptr(), options.len(), &mut output) };
+
+        let mut message = String::new();
+        if !output.is_null() {
+            message = unsafe { CStr::from_ptr(output) }
+                .to_string_lossy()
+                .to_string();
+            unsafe { (self.free_string)(output) };
+        }
+
+        match code {
+            0 => Ok(message),
+            _ => Err(message),
+        }
+    }
+}
+
+impl PluginInterface for NativePlugin {
+    fn run(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call(self.run, config).map(|_| ())
+    }
+
+    fn status(
+        &self,
+        config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        let output = self.call(self.status, config)?;
+        Ok(parse_key_value_status(output.as_bytes()))
+    }
+
+    fn rollback(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call(self.rollback, config).map(|_| ())
+    }
+}
+
+impl Drop for NativePlugin {
+    fn drop(&mut self) {
+        unsafe { (self.free)(self.plugin) };
+    }
+}
+
+unsafe fn get<T: Copy>(library: &Library, name: &str) -> Result<T, String> {
+    let symbol = format!("{}\0", name);
+    library
+        .get::<T>(symbol.as_bytes())
+        .map(|symbol| *symbol)
+        .map_err(|err| format!("Can't find {} in plugin library: {}", name, err))
+}
+
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    manifest: &PluginManifest,
+) -> Result<CallablePlugin, String> {
+    let path_to_library = path_to_plugin.join(
+        manifest
+            .run
+            .entrypoint
+            .clone()
+            .unwrap_or(PathBuf::from(library_file_name())),
+    );
+    if !path_to_library.exists() {
+        return Err(format!(
+            "No {} found for plugin: {}",
+            path_to_library.display(),
+            name
+        ));
+    }
+
+    let library = Library::new(&path_to_library)
+        .map_err(|err| format!("Can't load {}: {}", path_to_library.display(), err))?;
+
+    unsafe {
+        let version = get::<VersionFn>(&library, "paws_abi_version")?();
+        if version != ABI_VERSION {
+            return Err(format!(
+                "Plugin {} is built for ABI version {}, paws supports {}",
+                name, version, ABI_VERSION
+            ));
+        }
+
+        let init = get::<InitFn>(&library, "paws_init")?;
+        let run = get::<CallFn>(&library, "paws_run")?;
+        let status = get::<CallFn>(&library, "paws_status")?;
+        let rollback = get::<CallFn>(&library, "paws_rollback")?;
+        let free_string = get::<FreeStringFn>(&library, "paws_free_string")?;
+        let free = get::<FreeFn>(&library, "paws_free")?;
+
+        let plugin = init();
+        if plugin.is_null() {
+            return Err(format!("Plugin {} failed to initialize", name));
+        }
+
+        Ok(Box::new(NativePlugin {
+            plugin,
+            lock: Mutex::new(()),
+            run,
+            status,
+            rollback,
+            free_string,
+            free,
+            _library: library,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use paws_config::PluginManifest;
+
+    use super::{library_file_name, load};
+    use crate::plug::PluginStatusValue;
+
+    const PLUGIN: &str = r#"
+#[derive(Default)]
+struct Counter {
+    runs: u32,
+}
+
+impl paws_plugin::Plugin for Counter {
+    fn run(&mut self, options: &paws_plugin::Options) -> Result<(), String> {
+        if options.contains_key("fail") {
+            return Err("asked to fail".to_string());
+        }
+        self.runs += 1;
+        Ok(())
+    }
+
+    fn status(
+        &mut self,
+        options: &paws_plugin::Options,
+    ) -> Result<std::collections::HashMap<String, String>, String> {
+        Ok(std::collections::HashMap::from([
+            ("runs".to_string(), self.runs.to_string()),
+            ("target".to_string(), options["target"].clone()),
+        ]))
+    }
+}
+
+paws_plugin::export_plugin!(Counter);
+"#;
+
+    /// Builds a cdylib plugin with `export_plugin!`, the target dir is kept between test runs
+    fn build_plugin(dir: &Path) -> PathBuf {
+        let paws_plugin = Path::new(env!("CARGO_MANIFEST_DIR")).join("paws_plugin");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"counter\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+                 [lib]\ncrate-type = [\"cdylib\"]\n\n\
+                 [dependencies]\npaws_plugin = {{ path = {:?} }}\n\n[workspace]\n",
+                paws_plugin
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.join("src/lib.rs"), PLUGIN).unwrap();
+
+        let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/native-plugin-test");
+        let status = Command::new(env!("CARGO"))
+            .args(["build", "--offline", "--quiet", "--manifest-path"])
+            .arg(dir.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let library = target.join("debug").join(format!(
+            "{}counter.{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_EXTENSION
+        ));
+        let plugin_dir = dir.join("plugin");
+        std::fs::create_dir_all(&plugin_dir).unwrap();
+        std::fs::copy(library, plugin_dir.join(library_file_name())).unwrap();
+        plugin_dir
+    }
+
+    #[test]
+    #[ignore = "builds a cdylib with a nested cargo build, run with `cargo test native_plugin -- --ignored`"]
+    fn test_exported_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        let plugin_dir = build_plugin(dir.path());
+        let plugin = load("counter", &plugin_dir, &PluginManifest::default()).unwrap();
+        let config = HashMap::from([("target".to_string(), "db".to_string())]);
+
+        plugin.run(&config).unwrap();
+        let status = plugin.status(&config).unwrap();
+        assert!(matches!(status["runs"], PluginStatusValue::Int(1)));
+        assert!(matches!(&status["target"], PluginStatusValue::String(value) if value == "db"));
+
+        let fail = HashMap::from([("fail".to_string(), "yes".to_string())]);
+        assert_eq!(plugin.run(&fail), Err("asked to fail".to_string()));
+        assert_eq!(
+            plugin.rollback(&config),
+            Err("Plugin has no rollback".to_string())
+        );
+
+        let nul = HashMap::from([("target".to_string(), "d\0b".to_string())]);
+        assert!(plugin.run(&nul).is_err_and(|err| err.contains("NUL")));
+        let status = plugin.status(&config).unwrap();
+        assert!(matches!(status["runs"], PluginStatusValue::Int(1)));
+    }
+}
@@ -1,70 +1,737 @@
-use crate::plug::{unwrap_home_path, CallablePlugin, PluginInterface, PLUGINS_PATH};
+use crate::plug::{CallablePlugin, PluginInterface};
+use chrono::DateTime;
+use paws_config::{EntrypointOptions, PluginManifest};
 use pyo3::prelude::*;
 use pyo3::types::IntoPyDict;
-use pyo3::types::{PyList, PyModule};
+use pyo3::types::{PyDict, PyModule};
 use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::PluginStatusValue;
+use super::{PluginContext, PluginStatusValue};
 
-impl PluginInterface for pyo3::Py<PyAny> {
+pub(super) const LOADER: &str = include_str!("python_plugin/loader.py");
+pub(super) const PAWS_MODULE: &str = include_str!("python_plugin/paws.py");
+const DEFAULT_ENTRYPOINT: &str = "main.py";
+
+/// Package the plugin directory is imported as and `module:function` for every action
+pub(super) struct PythonEntrypoints {
+    pub package: String,
+    pub actions: HashMap<String, String>,
+}
+
+fn module_name(options: &EntrypointOptions) -> String {
+    if let Some(module) = &options.module {
+        return module.clone();
+    }
+    let entrypoint = options
+        .entrypoint
+        .clone()
+        .unwrap_or(PathBuf::from(DEFAULT_ENTRYPOINT))
+        .with_extension("");
+
+    entrypoint
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl PythonEntrypoints {
+    pub fn from_manifest(
+        name: &str,
+        path_to_plugin: &Path,
+        manifest: &PluginManifest,
+    ) -> Result<Self, String> {
+        if manifest.run.module.is_none() {
+            let path_to_main = path_to_plugin.join(
+                manifest
+                    .run
+                    .entrypoint
+                    .clone()
+                    .unwrap_or(PathBuf::from(DEFAULT_ENTRYPOINT)),
+            );
+            if !path_to_main.exists() {
+                return Err(format!(
+                    "No {} found for plugin: {}",
+                    path_to_main.display(),
+                    name
+                ));
+            }
+        }
+
+        let mut actions = HashMap::new();
+        for (action, options) in [
+            ("run", Some(&manifest.run)),
+            ("status", manifest.status.as_ref()),
+            ("rollback", manifest.rollback.as_ref()),
+            ("revert", manifest.revert.as_ref()),
+            ("check", manifest.check.as_ref()),
+            ("setup", manifest.setup.as_ref()),
+            ("teardown", manifest.teardown.as_ref()),
+        ] {
+            let options = options.cloned().unwrap_or_default().inherit(&manifest.run);
+            let function = options.function.clone().unwrap_or(action.to_string());
+            actions.insert(
+                action.to_string(),
+                format!("{}:{}", module_name(&options), function),
+            );
+        }
+
+        Ok(PythonEntrypoints {
+            package: package_name(name),
+            actions,
+        })
+    }
+}
+
+/// Unique importable name for the plugin package.
+/// Every byte but ASCII letters and digits is escaped as `_xx`, so names can't collide.
+fn package_name(name: &str) -> String {
+    let name: String = name
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() {
+                (byte as char).to_string()
+            } else {
+                format!("_{:02x}", byte)
+            }
+        })
+        .collect();
+    format!("paws_plugin_{}", name)
+}
+
+/// Python exception with the full traceback
+fn format_error(py: Python, err: PyErr) -> String {
+    let formatted = py
+        .import("traceback")
+        .and_then(|traceback| {
+            traceback.call_method1(
+                "format_exception",
+                (err.get_type(py), err.value(py), err.traceback(py)),
+            )
+        })
+        .and_then(|lines| lines.extract::<Vec<String>>());
+
+    match formatted {
+        Ok(lines) => lines.concat().trim_end().to_string(),
+        Err(_) => err.to_string(),
+    }
+}
+
+/// Loader module is created once, it owns the event loop and the `paws` module
+fn loader<'py>(py: Python<'py>) -> PyResult<&'py PyModule> {
+    if let Ok(loader) = py.import("paws_loader") {
+        return Ok(loader);
+    }
+    let loader = PyModule::from_code(py, LOADER, "paws_loader.py", "paws_loader")?;
+    loader.call_method1("install_paws", (PAWS_MODULE,))?;
+    Ok(loader)
+}
+
+/// `paws.context` of an embedded plugin
+#[pyclass(name = "Context")]
+struct PythonContext {
+    context: Arc<PluginContext>,
+}
+
+#[pymethods]
+impl PythonContext {
+    #[getter]
+    fn run_id(&self) -> String {
+        self.context.run_id.to_string()
+    }
+
+    #[getter]
+    fn instance_id(&self) -> String {
+        self.context.instance_id.clone()
+    }
+
+    #[getter]
+    fn iteration(&self) -> u64 {
+        self.context.iteration()
+    }
+
+    #[getter]
+    fn deadline(&self) -> Option<String> {
+        self.context.deadline_rfc3339()
+    }
+
+    #[getter]
+    fn state_dir(&self) -> PathBuf {
+        self.context.state_dir.clone()
+    }
+
+    fn log(&self, message: &str) {
+        self.context.log(message);
+    }
+
+    fn emit_metric(&self, name: &str, value: &PyAny, tags: HashMap<String, String>) {
+        let value = to_status_value(value);
+        self.context.emit_metric(name, (&value).into(), tags);
+    }
+
+    fn emit_event(
+        &self,
+        name: &str,
+        fields: HashMap<String, &PyAny>,
+        tags: HashMap<String, String>,
+    ) {
+        let fields = fields
+            .into_iter()
+            .map(|(key, value)| (key, (&to_status_value(value)).into()))
+            .collect();
+        self.context.emit_event(name, fields, tags);
+    }
+}
+
+fn import_functions<'py>(
+    py: Python<'py>,
+    path_to_plugin: &Path,
+    entrypoints: &PythonEntrypoints,
+) -> PyResult<&'py PyDict> {
+    let loader = loader(py)?;
+    loader.call_method1(
+        "import_plugin",
+        (
+            path_to_plugin.to_string_lossy().to_string(),
+            &entrypoints.package,
+        ),
+    )?;
+    let functions = loader.call_method1(
+        "resolve",
+        (
+            &entrypoints.package,
+            entrypoints.actions.clone().into_py_dict(py),
+        ),
+    )?;
+
+    Ok(functions.downcast()?)
+}
+
+/// Module functions or methods of a `Plugin` class instance living for the whole run
+struct PythonPlugin {
+    run: Py<PyAny>,
+    status: Option<Py<PyAny>>,
+    rollback: Option<Py<PyAny>>,
+    revert: Option<Py<PyAny>>,
+    check: Option<Py<PyAny>>,
+    setup: Option<Py<PyAny>>,
+    teardown: Option<Py<PyAny>>,
+    context: Py<PythonContext>,
+}
+
+/// `datetime` and `timedelta` objects, naive datetimes are in local time
+fn to_time_value(value: &PyAny) -> PyResult<Option<PluginStatusValue>> {
+    let datetime = value.py().import("datetime")?;
+    if value.is_instance(datetime.getattr("datetime")?.downcast()?)? {
+        let seconds: f64 = value.call_method0("timestamp")?.extract()?;
+        let timestamp = DateTime::from_timestamp_micros((seconds * 1e6).round() as i64);
+        return Ok(timestamp.map(PluginStatusValue::Timestamp));
+    }
+    if value.is_instance(datetime.getattr("timedelta")?.downcast()?)? {
+        let seconds: f64 = value.call_method0("total_seconds")?.extract()?;
+        let duration = Duration::try_from_secs_f64(seconds).ok();
+        return Ok(duration.map(PluginStatusValue::Duration));
+    }
+    Ok(None)
+}
+
+fn to_status_value(value: &PyAny) -> PluginStatusValue {
+    if let Ok(value) = value.extract::<bool>() {
+        PluginStatusValue::Bool(value)
+    } else if let Ok(value) = value.extract::<i64>() {
+        PluginStatusValue::Int(value)
+    } else if let Ok(value) = value.extract::<u64>() {
+        PluginStatusValue::UInt(value)
+    } else if let Ok(Some(value)) = to_time_value(value) {
+        value
+    } else if let Ok(value) = value.extract::<f64>() {
+        PluginStatusValue::Float(value)
+    } else {
+        PluginStatusValue::String(value.to_string())
+    }
+}
+
+impl PythonPlugin {
+    /// Calls the function through the loader, which sets `paws.context`
+    /// and runs coroutines on the paws event loop
+    fn invoke<'py>(
+        &self,
+        py: Python<'py>,
+        function: &Py<PyAny>,
+        config: &HashMap<String, String>,
+    ) -> PyResult<&'py PyAny> {
+        loader(py)?.call_method1(
+            "invoke",
+            (function, config.clone().into_py_dict(py), &self.context),
+        )
+    }
+
+    fn call_with_config(
+        &self,
+        function: &Py<PyAny>,
+        config: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        Python::with_gil(|py| {
+            self.invoke(py, function, config)
+                .map(|_| ())
+                .map_err(|err| format_error(py, err))
+        })
+    }
+
+    fn call_status(
+        &self,
+        function: &Py<PyAny>,
+        config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        Python::with_gil(|py| {
+            let result: &PyDict = self
+                .invoke(py, function, config)
+                .map_err(|err| format_error(py, err))?
+                .downcast()
+                .map_err(|_| "status function must return a dict".to_string())?;
+
+            Ok(result
+                .iter()
+                .map(|(key, value)| (key.to_string(), to_status_value(value)))
+                .collect())
+        })
+    }
+}
+
+impl PluginInterface for PythonPlugin {
     fn run(&self, config: &HashMap<String, String>) -> Result<(), String> {
-        let mut pyconfig = HashMap::new();
-        pyconfig.insert("config", &config);
+        self.call_with_config(&self.run, config)
+    }
+
+    fn status(
+        &self,
+        config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        match &self.status {
+            Some(status) => self.call_status(status, config),
+            None => Err("Plugin has no status function".to_string()),
+        }
+    }
 
-        if let Err(err) = Python::with_gil(|py| self.call(py, (), Some(pyconfig.into_py_dict(py))))
-        {
-            return Err(format!("{}", err));
+    fn rollback(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.rollback {
+            Some(rollback) => self.call_with_config(rollback, config),
+            None => Err("Plugin has no rollback function".to_string()),
         }
+    }
 
-        Ok(())
+    fn revert(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.revert {
+            Some(revert) => self.call_with_config(revert, config),
+            None => Err("Plugin has no revert or rollback function".to_string()),
+        }
     }
 
-    fn status(&self, _: &HashMap<String, String>) -> Result<HashMap<String, PluginStatusValue>, String> {
-        unimplemented!("Python plugins do not support status checks now")
+    fn check(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.check {
+            Some(check) => self.call_with_config(check, config),
+            None => Ok(()),
+        }
+    }
+
+    fn setup(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.setup {
+            Some(setup) => self.call_with_config(setup, config),
+            None => Ok(()),
+        }
+    }
+
+    fn teardown(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        match &self.teardown {
+            Some(teardown) => self.call_with_config(teardown, config),
+            None => Ok(()),
+        }
     }
 }
 
-pub fn load(name: &str) -> Result<CallablePlugin, String> {
-    let plugins_path = unwrap_home_path(PLUGINS_PATH);
-    let plugins_dirname = plugins_path
-        .to_str()
-        .expect("Can't find home directory for the current user");
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    manifest: &PluginManifest,
+    context: &Arc<PluginContext>,
+) -> Result<CallablePlugin, String> {
+    let entrypoints = PythonEntrypoints::from_manifest(name, path_to_plugin, manifest)?;
 
-    let entrypoint_path = format!("{}/{}/main.py", &plugins_dirname, name);
-    let path_to_main = Path::new(&entrypoint_path);
+    let app = Python::with_gil(|py| {
+        let functions = import_functions(py, path_to_plugin, &entrypoints)
+            .map_err(|err| format!("Can't load plugin {}: {}", name, format_error(py, err)))?;
+        let function = |action: &str| functions.get_item(action).map(Into::into);
 
-    if !path_to_main.exists() {
-        println!();
-        return Err(format!("No main.py found for plugin: {}", name));
+        let Some(run) = function("run") else {
+            return Err(format!("Can't find run function for plugin {}", name));
+        };
+        Ok(PythonPlugin {
+            run,
+            status: function("status"),
+            rollback: function("rollback"),
+            revert: function("revert"),
+            check: function("check"),
+            setup: function("setup"),
+            teardown: function("teardown"),
+            context: Py::new(
+                py,
+                PythonContext {
+                    context: context.clone(),
+                },
+            )
+            .map_err(|err| format_error(py, err))?,
+        })
+    })?;
+
+    Ok(Box::new(app))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use paws_config::{EntrypointOptions, PluginManifest};
+
+    use super::load;
+    use crate::plug::context::{
+        assert_reported, recording_context, standalone_context, REPORTING_PLUGIN,
+    };
+    use crate::plug::{PluginContext, PluginStatusValue};
+
+    #[test]
+    fn test_package_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("dropper")).unwrap();
+        std::fs::write(dir.path().join("dropper/__init__.py"), "").unwrap();
+        std::fs::write(
+            dir.path().join("dropper/checks.py"),
+            "import os\n\ndef check(config):\n    return {'file': os.path.basename(__file__)}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("dropper/main.py"),
+            "from .checks import check\n\ndef inject(config):\n    raise ValueError('broken ' + config['target'])\n",
+        )
+        .unwrap();
+        let manifest = PluginManifest {
+            run: EntrypointOptions {
+                module: Some("dropper.main".to_string()),
+                function: Some("inject".to_string()),
+                ..Default::default()
+            },
+            status: Some(EntrypointOptions {
+                module: Some("dropper.checks".to_string()),
+                function: Some("check".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let plugin = load(
+            "package-plugin",
+            dir.path(),
+            &manifest,
+            &standalone_context(),
+        )
+        .unwrap();
+        let config = HashMap::from([("target".to_string(), "db".to_string())]);
+
+        let status = plugin.status(&config).unwrap();
+        assert!(
+            matches!(&status["file"], PluginStatusValue::String(value) if value == "checks.py")
+        );
+        let err = plugin.run(&config).unwrap_err();
+        assert!(err.starts_with("Traceback"));
+        assert!(err.contains("main.py"));
+        assert!(err.ends_with("ValueError: broken db"));
+        assert!(plugin.rollback(&config).is_err());
+        assert!(plugin.revert(&config).is_err());
     }
-    match fs::read_to_string(path_to_main) {
-        Ok(code) => {
-            let app = Python::with_gil(|py| {
-                let syspath: &PyList = py
-                    .import("sys")
-                    .expect("Python can't import sys module. Nobody knows why")
-                    .getattr("path")
-                    .unwrap()
-                    .downcast::<PyList>()
-                    .expect("Somehow sys.path is not a valid pyhon list");
 
-                syspath
-                    .insert(0, path_to_main)
-                    .expect("Can't insert to Python path");
+    #[test]
+    fn test_plugin_dir_not_on_shared_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("helpers.py"), "").unwrap();
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+import os
+import sys
+
+def run(config):
+    pass
 
-                let app: Py<PyAny> = PyModule::from_code(py, &code, "", "")
-                    .unwrap_or_else(|_| panic!("Can't find main.py for plugin {}", name))
-                    .getattr("run")
-                    .unwrap_or_else(|_| {
-                        panic!("Can't find run function in main.py for plugin {}", name)
-                    })
-                    .into();
-                app
-            });
-            Ok(Box::new(app) as CallablePlugin)
+def status(config):
+    return {'on_path': os.path.dirname(__file__) in sys.path, 'helpers': 'helpers' in sys.modules}
+",
+        )
+        .unwrap();
+        let plugin = load(
+            "isolated-plugin",
+            dir.path(),
+            &PluginManifest::default(),
+            &standalone_context(),
+        )
+        .unwrap();
+
+        let status = plugin.status(&HashMap::new()).unwrap();
+        assert!(matches!(status["on_path"], PluginStatusValue::Bool(false)));
+        assert!(matches!(status["helpers"], PluginStatusValue::Bool(false)));
+    }
+
+    #[test]
+    fn test_similar_names_are_different_packages() {
+        let load_named = |name: &str| {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(
+                dir.path().join("main.py"),
+                format!(
+                    "def run(config):\n    pass\n\ndef status(config):\n    return {{'name': '{}'}}\n",
+                    name
+                ),
+            )
+            .unwrap();
+            load(
+                name,
+                dir.path(),
+                &PluginManifest::default(),
+                &standalone_context(),
+            )
+            .unwrap()
+        };
+
+        let dashed = load_named("twin-plugin");
+        let underscored = load_named("twin_plugin");
+
+        for (plugin, name) in [(dashed, "twin-plugin"), (underscored, "twin_plugin")] {
+            let status = plugin.status(&HashMap::new()).unwrap();
+            assert!(matches!(&status["name"], PluginStatusValue::String(value) if value == name));
         }
-        Err(_) => Err("Could not read main.py code".to_string()),
+    }
+
+    #[test]
+    fn test_revert_falls_back_to_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+active = False
+
+def run(config):
+    global active
+    active = True
+
+def rollback(config):
+    global active
+    active = False
+
+def status(config):
+    return {'active': active}
+",
+        )
+        .unwrap();
+        let plugin = load(
+            "revert-plugin",
+            dir.path(),
+            &PluginManifest::default(),
+            &standalone_context(),
+        )
+        .unwrap();
+        let config = HashMap::new();
+
+        plugin.run(&config).unwrap();
+        assert!(matches!(
+            plugin.status(&config).unwrap()["active"],
+            PluginStatusValue::Bool(true)
+        ));
+        plugin.revert(&config).unwrap();
+        assert!(matches!(
+            plugin.status(&config).unwrap()["active"],
+            PluginStatusValue::Bool(false)
+        ));
+    }
+
+    #[test]
+    fn test_class_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+import datetime
+
+class Plugin:
+    def setup(self, config):
+        self.target = config['target']
+        self.runs = 0
+
+    def run(self):
+        self.runs += 1
+
+    def status(self):
+        return {
+            'target': self.target,
+            'runs': self.runs,
+            'up': True,
+            'sent': 2 ** 63,
+            'restarted': datetime.datetime(2024, 1, 1, tzinfo=datetime.timezone.utc),
+            'uptime': datetime.timedelta(minutes=1),
+        }
+
+    def teardown(self):
+        raise RuntimeError('closed ' + self.target)
+",
+        )
+        .unwrap();
+        let plugin = load(
+            "class-plugin",
+            dir.path(),
+            &PluginManifest::default(),
+            &standalone_context(),
+        )
+        .unwrap();
+        let config = HashMap::from([("target".to_string(), "db".to_string())]);
+
+        plugin.setup(&config).unwrap();
+        plugin.run(&HashMap::new()).unwrap();
+        plugin.run(&HashMap::new()).unwrap();
+        let status = plugin.status(&HashMap::new()).unwrap();
+        assert!(matches!(status["runs"], PluginStatusValue::Int(2)));
+        assert!(matches!(&status["target"], PluginStatusValue::String(value) if value == "db"));
+        assert!(matches!(status["up"], PluginStatusValue::Bool(true)));
+        assert!(matches!(status["sent"], PluginStatusValue::UInt(value) if value == 1 << 63));
+        assert!(
+            matches!(status["restarted"], PluginStatusValue::Timestamp(value) if value.timestamp() == 1704067200)
+        );
+        assert!(
+            matches!(status["uptime"], PluginStatusValue::Duration(value) if value.as_secs() == 60)
+        );
+        assert!(plugin.rollback(&config).is_err());
+        assert!(plugin
+            .teardown(&config)
+            .is_err_and(|err| err.ends_with("RuntimeError: closed db")));
+    }
+
+    #[test]
+    fn test_async_plugins_share_event_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        // The first call finishes only if the second one runs on the loop meanwhile
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+import asyncio
+
+calls = 0
+event = None
+
+async def run(config):
+    global calls, event
+    calls += 1
+    if event is None:
+        event = asyncio.Event()
+    if calls == 2:
+        event.set()
+        return
+    await asyncio.wait_for(event.wait(), 5)
+
+async def status(config):
+    await asyncio.sleep(0)
+    return {'calls': calls}
+",
+        )
+        .unwrap();
+        let plugin = load(
+            "async-plugin",
+            dir.path(),
+            &PluginManifest::default(),
+            &standalone_context(),
+        )
+        .unwrap();
+        let config = HashMap::new();
+
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| plugin.run(&config));
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            let second = scope.spawn(|| plugin.run(&config));
+            assert!(first.join().unwrap().is_ok());
+            assert!(second.join().unwrap().is_ok());
+        });
+        let status = plugin.status(&config).unwrap();
+        assert!(matches!(status["calls"], PluginStatusValue::Int(2)));
+    }
+
+    #[test]
+    fn test_paws_module() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.py"), REPORTING_PLUGIN).unwrap();
+        let (context, wait_for) = recording_context("reporter-1");
+        let plugin = load(
+            "reporting",
+            dir.path(),
+            &PluginManifest::default(),
+            &context,
+        )
+        .unwrap();
+
+        context.next_iteration();
+        plugin.run(&HashMap::new()).unwrap();
+
+        assert_reported(&wait_for(2));
+    }
+
+    #[test]
+    fn test_state() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+import paws
+
+def run(config):
+    paws.state.setdefault('original', config['clock'])
+    paws.state['runs'] = int(paws.state.get('runs', 0)) + 1
+
+def rollback(config):
+    del paws.state['original']
+
+def status(config):
+    return dict(paws.state)
+",
+        )
+        .unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let mut context = PluginContext::standalone("state");
+        context.state_dir = state.path().join("state");
+        let plugin = load(
+            "state-plugin",
+            dir.path(),
+            &PluginManifest::default(),
+            &Arc::new(context),
+        )
+        .unwrap();
+
+        plugin
+            .run(&HashMap::from([("clock".to_string(), "ntp".to_string())]))
+            .unwrap();
+        plugin
+            .run(&HashMap::from([(
+                "clock".to_string(),
+                "manual".to_string(),
+            )]))
+            .unwrap();
+        let status = plugin.status(&HashMap::new()).unwrap();
+        assert!(matches!(&status["original"], PluginStatusValue::String(value) if value == "ntp"));
+        assert!(matches!(&status["runs"], PluginStatusValue::String(value) if value == "2"));
+        assert_eq!(
+            std::fs::read_to_string(state.path().join("state/original")).unwrap(),
+            "ntp"
+        );
+
+        plugin.rollback(&HashMap::new()).unwrap();
+        assert!(plugin
+            .rollback(&HashMap::new())
+            .is_err_and(|err| err.contains("KeyError")));
     }
 }
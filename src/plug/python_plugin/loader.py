@@ -0,0 +1,139 @@
+"""Imports a plugin directory as a Python package and calls its functions.
+
+Shared by embedded and out-of-process Python plugins. The package gets a name
+unique to the plugin, so modules of different plugins don't clash and relative
+imports, `__file__` and tracebacks work as in any other package.
+"""
+
+import asyncio
+import importlib
+import importlib.machinery
+import importlib.util
+import inspect
+import os
+import sys
+import threading
+import types
+
+# Event loop async plugins run on, shared by all plugins in the interpreter
+_loop = None
+_loop_lock = threading.Lock()
+
+
+def install_paws(source):
+    """Makes the paws runtime module importable by plugins"""
+    if "paws" in sys.modules:
+        return
+    module = types.ModuleType("paws")
+    module.__file__ = "paws.py"
+    exec(compile(source, "paws.py", "exec"), module.__dict__)
+    sys.modules["paws"] = module
+
+
+def import_plugin(path, package, own_process=False):
+    """Plugins sharing the interpreter only see their modules through the package,
+    a plugin with a process of its own can import them by absolute name too"""
+    if package in sys.modules:
+        return sys.modules[package]
+
+    path = os.path.abspath(path)
+    if own_process and path not in sys.path:
+        sys.path.insert(0, path)
+
+    init = os.path.join(path, "__init__.py")
+    if os.path.exists(init):
+        spec = importlib.util.spec_from_file_location(
+            package, init, submodule_search_locations=[path]
+        )
+    else:
+        spec = importlib.machinery.ModuleSpec(package, None, is_package=True)
+        spec.submodule_search_locations = [path]
+
+    module = importlib.util.module_from_spec(spec)
+    sys.modules[package] = module
+    try:
+        if spec.loader is not None:
+            spec.loader.exec_module(module)
+    except BaseException:
+        del sys.modules[package]
+        raise
+    return module
+
+
+def resolve(package, entrypoints):
+    """Maps actions to functions called with the plugin config:
+    `{"run": "main:run"}` -> `{"run": <function run>}`.
+    A `Plugin` class in the run module is used instead of functions if it's defined.
+    Actions whose function is not defined are left out, `revert` falls back to `rollback`."""
+    module, _, _ = entrypoints["run"].partition(":")
+    instance = instantiate(importlib.import_module(package + "." + module))
+    if instance is not None:
+        functions = bind(instance)
+    else:
+        functions = {}
+        for action, entrypoint in entrypoints.items():
+            module, _, function = entrypoint.partition(":")
+            module = importlib.import_module(package + "." + module)
+            function = getattr(module, function, None)
+            if callable(function):
+                functions[action] = function
+
+    if "revert" not in functions and "rollback" in functions:
+        functions["revert"] = functions["rollback"]
+    return functions
+
+
+def instantiate(module):
+    """Instance of the `Plugin` class if the module defines one"""
+    plugin = getattr(module, "Plugin", None)
+    if isinstance(plugin, type) and callable(getattr(plugin, "run", None)):
+        return plugin()
+    return None
+
+
+def bind(instance):
+    """Methods of a class based plugin, only `check` and `setup` get the config"""
+    functions = {}
+    for action in ("check", "setup", "run", "status", "rollback", "revert", "teardown"):
+        method = getattr(instance, action, None)
+        if not callable(method):
+            continue
+        if action in ("check", "setup"):
+            functions[action] = lambda config, method=method: method(config)
+        else:
+            functions[action] = lambda config, method=method: method()
+    return functions
+
+
+def event_loop():
+    global _loop
+    with _loop_lock:
+        if _loop is None:
+            _loop = asyncio.new_event_loop()
+            threading.Thread(
+                target=_loop.run_forever, name="paws-asyncio", daemon=True
+            ).start()
+        return _loop
+
+
+async def _wait(awaitable, context):
+    # Tasks run in a copy of the loop thread context
+    sys.modules["paws"]._bind(context)
+    return await awaitable
+
+
+def invoke(function, config, context=None):
+    """Calls a plugin function with `paws.context` set to the plugin instance context.
+    Coroutines are run on the paws event loop and the calling thread waits for the result."""
+    paws = sys.modules["paws"]
+    token = paws._bind(context)
+    try:
+        result = function(config=config)
+        if inspect.isawaitable(result):
+            future = asyncio.run_coroutine_threadsafe(
+                _wait(result, context), event_loop()
+            )
+            return future.result()
+        return result
+    finally:
+        paws._unbind(token)
@@ -0,0 +1,141 @@
+"""Runtime API for Python plugins, available as `import paws`:
+
+    paws.context.run_id, paws.context.instance_id, paws.context.iteration, paws.context.deadline,
+    paws.context.state_dir
+    paws.logger.info("dropping packets")
+    paws.emit_metric("dropped_packets", 10, tags={"iface": "eth0"})
+    paws.emit_event("container_restarted", fields={"container": "db"})
+    paws.state["original_clock"] = "ntp"
+
+The context belongs to the plugin instance paws is currently calling,
+it's not available outside of plugin calls.
+"""
+
+import contextvars
+import datetime
+import logging
+import os
+import sys
+import tempfile
+from collections.abc import MutableMapping
+
+_current = contextvars.ContextVar("paws_context", default=None)
+
+
+def _bind(context):
+    return _current.set(context)
+
+
+def _unbind(token):
+    _current.reset(token)
+
+
+def _get():
+    context = _current.get()
+    if context is None:
+        raise RuntimeError("paws context is only available while paws calls the plugin")
+    return context
+
+
+class _Context:
+    @property
+    def run_id(self):
+        return _get().run_id
+
+    @property
+    def instance_id(self):
+        return _get().instance_id
+
+    @property
+    def iteration(self):
+        return _get().iteration
+
+    @property
+    def state_dir(self):
+        return _get().state_dir
+
+    @property
+    def deadline(self):
+        deadline = _get().deadline
+        return datetime.datetime.fromisoformat(deadline) if deadline else None
+
+
+context = _Context()
+
+
+class _Handler(logging.Handler):
+    def emit(self, record):
+        message = self.format(record)
+        current = _current.get()
+        if current is None:
+            print(message, file=sys.stderr)
+        else:
+            current.log(message)
+
+
+logger = logging.getLogger("paws")
+logger.setLevel(logging.INFO)
+logger.addHandler(_Handler())
+logger.propagate = False
+
+
+def _tags(tags):
+    return {str(key): str(value) for key, value in (tags or {}).items()}
+
+
+def emit_metric(name, value, tags=None):
+    _get().emit_metric(str(name), value, _tags(tags))
+
+
+def emit_event(name, fields=None, tags=None):
+    fields = {str(key): value for key, value in (fields or {}).items()}
+    _get().emit_event(str(name), fields, _tags(tags))
+
+
+class _State(MutableMapping):
+    """String values of the plugin instance kept between runs and paws restarts,
+    stored as a file per key in `paws.context.state_dir`"""
+
+    def _dir(self):
+        return _get().state_dir
+
+    def _path(self, key):
+        key = str(key)
+        if not key or key.startswith(".") or os.sep in key or "/" in key:
+            raise KeyError(key)
+        return os.path.join(self._dir(), key)
+
+    def __getitem__(self, key):
+        try:
+            with open(self._path(key)) as file:
+                return file.read()
+        except FileNotFoundError:
+            raise KeyError(key) from None
+
+    def __setitem__(self, key, value):
+        path = self._path(key)
+        os.makedirs(self._dir(), exist_ok=True)
+        # Written in full or not at all, even if paws is killed meanwhile
+        fd, temp = tempfile.mkstemp(dir=self._dir(), prefix=".")
+        with os.fdopen(fd, "w") as file:
+            file.write(str(value))
+        os.replace(temp, path)
+
+    def __delitem__(self, key):
+        try:
+            os.remove(self._path(key))
+        except FileNotFoundError:
+            raise KeyError(key) from None
+
+    def __iter__(self):
+        try:
+            names = sorted(os.listdir(self._dir()))
+        except FileNotFoundError:
+            return iter(())
+        return iter([name for name in names if not name.startswith(".")])
+
+    def __len__(self):
+        return len(list(iter(self)))
+
+
+state = _State()
@@ -0,0 +1,488 @@
+use crate::plug::{CallablePlugin, PluginInterface};
+use paws_config::PluginManifest;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::context::{json_status_value, ReportLine};
+use super::python_plugin::{PythonEntrypoints, LOADER, PAWS_MODULE};
+use super::{PluginContext, PluginStatusValue};
+
+const SHIM: &str = include_str!("python_process_plugin/shim.py");
+/// Longest a call may take unless configured, the process is restarted after that
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Response to a request or a call to the `paws` module made while handling it
+#[derive(Debug, Deserialize)]
+struct Response {
+    #[serde(default)]
+    ok: bool,
+    error: Option<String>,
+    functions: Option<Vec<String>>,
+    status: Option<HashMap<String, Value>>,
+    #[serde(flatten)]
+    report: ReportLine,
+}
+
+/// Running interpreter with the shim loaded, one request at a time
+struct ShimProcess {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines of stdout, read by a thread so a hung plugin can be timed out
+    stdout: Receiver<std::io::Result<String>>,
+    timeout: Duration,
+}
+
+impl ShimProcess {
+    fn spawn(
+        interpreter: &Path,
+        path_to_plugin: &Path,
+        entrypoints: &PythonEntrypoints,
+        timeout: Duration,
+        context: &PluginContext,
+    ) -> Result<(Self, Response), String> {
+        let mut child = Command::new(interpreter)
+            .arg("-u")
+            .arg("-c")
+            .arg(format!("{}\n{}", LOADER, SHIM))
+            .arg(path_to_plugin)
+            .arg(&entrypoints.package)
+            .arg(json!(entrypoints.actions).to_string())
+            .arg(PAWS_MODULE)
+            .current_dir(path_to_plugin)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Failed to start {}: {}", interpreter.display(), err))?;
+
+        // unwrap: both are piped above
+        let stdin = child.stdin.take().unwrap();
+        let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        let (sender, stdout) = mpsc::channel();
+        // Ends once the process exits and its stdout is closed
+        thread::spawn(move || {
+            for line in lines {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut process = ShimProcess {
+            child,
+            stdin,
+            stdout,
+            timeout,
+        };
+        let handshake = process.read(context)?;
+
+        Ok((process, handshake))
+    }
+
+    /// Fails if the response takes longer than the timeout, the process has to be dropped then
+    fn read(&mut self, context: &PluginContext) -> Result<Response, String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let line = match self.stdout.recv_timeout(left) {
+                Ok(line) => {
+                    line.map_err(|err| format!("Can't read from python process: {}", err))?
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!(
+                        "Python process didn't respond in {}s, restarting it",
+                        self.timeout.as_secs_f64()
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let status = self
+                        .child
+                        .wait()
+                        .map(|status| status.to_string())
+                        .unwrap_or_else(|err| err.to_string());
+                    return Err(format!("Python process exited: {}", status));
+                }
+            };
+
+            let response: Response = serde_json::from_str(&line)
+                .map_err(|err| format!("Invalid response from plugin: {}", err))?;
+            if !response.report.send(context) {
+                return Ok(response);
+            }
+        }
+    }
+
+    fn request(
+        &mut self,
+        action: &str,
+        config: &HashMap<String, String>,
+        context: &PluginContext,
+    ) -> Result<Response, String> {
+        let request = json!({
+            "action": action,
+            "config": config,
+            "context": {
+                "run_id": context.run_id.to_string(),
+                "instance_id": context.instance_id,
+                "iteration": context.iteration(),
+                "deadline": context.deadline_rfc3339(),
+                "state_dir": context.state_dir,
+            },
+        });
+        writeln!(self.stdin, "{}", request)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| format!("Can't write to python process: {}", err))?;
+        self.read(context)
+    }
+}
+
+impl Drop for ShimProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Python plugin running in its own interpreter process.
+/// A crashed or hung process is started again on the next call and set up again if it was.
+struct PythonProcessPlugin {
+    interpreter: PathBuf,
+    path_to_plugin: PathBuf,
+    entrypoints: PythonEntrypoints,
+    timeout: Duration,
+    process: Mutex<Option<ShimProcess>>,
+    setup_config: Mutex<Option<HashMap<String, String>>>,
+    context: Arc<PluginContext>,
+}
+
+impl PythonProcessPlugin {
+    fn call(&self, action: &str, config: &HashMap<String, String>) -> Result<Response, String> {
+        let mut process = self.process.lock().unwrap();
+        if process.is_none() {
+            let (started, handshake) = ShimProcess::spawn(
+                &self.interpreter,
+                &self.path_to_plugin,
+                &self.entrypoints,
+                self.timeout,
+                &self.context,
+            )?;
+            check_handshake(handshake)?;
+            *process = Some(started);
+
+            if let Some(setup_config) = self.setup_config.lock().unwrap().as_ref() {
+                // unwrap: started above
+                let setup = process
+                    .as_mut()
+                    .unwrap()
+                    .request("setup", setup_config, &self.context);
+                if let Err(err) = setup {
+                    *process = None;
+                    return Err(err);
+                }
+            }
+        }
+
+        // unwrap: started above
+        let response = process
+            .as_mut()
+            .unwrap()
+            .request(action, config, &self.context);
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                *process = None;
+                return Err(err);
+            }
+        };
+
+        match response.ok {
+            true => Ok(response),
+            false => Err(response.error.unwrap_or_default()),
+        }
+    }
+}
+
+fn check_handshake(handshake: Response) -> Result<(), String> {
+    if !handshake.ok {
+        return Err(handshake.error.unwrap_or_default());
+    }
+    if !handshake
+        .functions
+        .unwrap_or_default()
+        .iter()
+        .any(|function| function == "run")
+    {
+        return Err("Can't find run function".to_string());
+    }
+
+    Ok(())
+}
+
+impl PluginInterface for PythonProcessPlugin {
+    fn run(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call("run", config).map(|_| ())
+    }
+
+    fn status(
+        &self,
+        config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        let response = self.call("status", config)?;
+        Ok(response
+            .status
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, json_status_value(value)))
+            .collect())
+    }
+
+    fn rollback(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call("rollback", config).map(|_| ())
+    }
+
+    fn revert(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call("revert", config).map(|_| ())
+    }
+
+    fn check(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call("check", config).map(|_| ())
+    }
+
+    fn setup(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        self.call("setup", config)?;
+        *self.setup_config.lock().unwrap() = Some(config.clone());
+        Ok(())
+    }
+
+    fn teardown(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        *self.setup_config.lock().unwrap() = None;
+        self.call("teardown", config).map(|_| ())
+    }
+}
+
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    manifest: &PluginManifest,
+    interpreter: &Path,
+    timeout: Option<Duration>,
+    context: &Arc<PluginContext>,
+) -> Result<CallablePlugin, String> {
+    let entrypoints = PythonEntrypoints::from_manifest(name, path_to_plugin, manifest)?;
+    let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+
+    let (process, handshake) =
+        ShimProcess::spawn(interpreter, path_to_plugin, &entrypoints, timeout, context)?;
+    check_handshake(handshake).map_err(|err| format!("Can't load plugin {}: {}", name, err))?;
+
+    Ok(Box::new(PythonProcessPlugin {
+        interpreter: interpreter.to_path_buf(),
+        path_to_plugin: path_to_plugin.to_path_buf(),
+        entrypoints,
+        timeout,
+        process: Mutex::new(Some(process)),
+        setup_config: Mutex::new(None),
+        context: context.clone(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use paws_config::PluginManifest;
+
+    use super::load;
+    use crate::plug::context::{
+        assert_reported, recording_context, standalone_context, REPORTING_PLUGIN,
+    };
+    use crate::plug::PluginStatusValue;
+
+    const PLUGIN: &str = "
+import datetime
+import os
+
+runs = 0
+
+def run(config):
+    global runs
+    runs += 1
+    print('printed output does not break the protocol')
+    if config.get('crash'):
+        os._exit(1)
+
+def status(config):
+    if config.get('negative'):
+        return {'uptime': datetime.timedelta(seconds=-1.5)}
+    return {
+        'runs': runs,
+        'target': config['target'],
+        'load': 0.5,
+        'up': True,
+        'restarted': datetime.datetime(2024, 1, 1, tzinfo=datetime.timezone.utc),
+        'uptime': datetime.timedelta(seconds=1.5),
+        'tick': datetime.timedelta(microseconds=1),
+    }
+";
+
+    #[test]
+    fn test_out_of_process_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.py"), PLUGIN).unwrap();
+        let plugin = load(
+            "counter",
+            dir.path(),
+            &PluginManifest::default(),
+            Path::new("python3"),
+            None,
+            &standalone_context(),
+        )
+        .unwrap();
+        let config = HashMap::from([("target".to_string(), "db".to_string())]);
+
+        assert!(plugin.run(&config).is_ok());
+        let status = plugin.status(&config).unwrap();
+        assert!(matches!(status["runs"], PluginStatusValue::Int(1)));
+        assert!(matches!(status["load"], PluginStatusValue::Float(value) if value == 0.5));
+        assert!(matches!(&status["target"], PluginStatusValue::String(value) if value == "db"));
+        assert!(matches!(status["up"], PluginStatusValue::Bool(true)));
+        assert!(
+            matches!(status["restarted"], PluginStatusValue::Timestamp(value) if value.timestamp() == 1704067200)
+        );
+        assert!(
+            matches!(status["uptime"], PluginStatusValue::Duration(value) if value.as_millis() == 1500)
+        );
+        assert!(matches!(status["tick"], PluginStatusValue::Duration(value) if value.is_zero()));
+        let negative = HashMap::from([("negative".to_string(), "yes".to_string())]);
+        assert!(plugin
+            .status(&negative)
+            .is_err_and(|err| err.contains("Negative durations")));
+        assert!(plugin.rollback(&config).is_err());
+
+        let crash = HashMap::from([("crash".to_string(), "yes".to_string())]);
+        assert!(plugin.run(&crash).is_err());
+        // Crashed process is replaced with a fresh one
+        let status = plugin.status(&config).unwrap();
+        assert!(matches!(status["runs"], PluginStatusValue::Int(0)));
+    }
+
+    #[test]
+    fn test_import_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.py"), "import no_such_module\n").unwrap();
+
+        let result = load(
+            "broken",
+            dir.path(),
+            &PluginManifest::default(),
+            Path::new("python3"),
+            None,
+            &standalone_context(),
+        );
+        assert!(result.is_err_and(|err| err.contains("no_such_module")));
+    }
+
+    #[test]
+    fn test_class_plugin_set_up_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+import os
+
+class Plugin:
+    def setup(self, config):
+        self.target = config['target']
+
+    def run(self):
+        os._exit(1)
+
+    def status(self):
+        return {'target': self.target}
+",
+        )
+        .unwrap();
+        let plugin = load(
+            "class-counter",
+            dir.path(),
+            &PluginManifest::default(),
+            Path::new("python3"),
+            None,
+            &standalone_context(),
+        )
+        .unwrap();
+        let config = HashMap::from([("target".to_string(), "db".to_string())]);
+
+        plugin.setup(&config).unwrap();
+        assert!(plugin.run(&HashMap::new()).is_err());
+        let status = plugin.status(&HashMap::new()).unwrap();
+        assert!(matches!(&status["target"], PluginStatusValue::String(value) if value == "db"));
+        assert!(plugin.teardown(&config).is_ok());
+    }
+
+    #[test]
+    fn test_paws_module_through_shim() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.py"), REPORTING_PLUGIN).unwrap();
+        let (context, wait_for) = recording_context("reporter-1");
+        let plugin = load(
+            "reporting",
+            dir.path(),
+            &PluginManifest::default(),
+            Path::new("python3"),
+            None,
+            &context,
+        )
+        .unwrap();
+
+        context.next_iteration();
+        plugin.run(&HashMap::new()).unwrap();
+
+        assert_reported(&wait_for(2));
+    }
+
+    #[test]
+    fn test_hung_process_restarted() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.py"),
+            "
+import time
+
+def run(config):
+    if config.get('hang'):
+        time.sleep(60)
+
+def status(config):
+    return {'up': True}
+",
+        )
+        .unwrap();
+        let plugin = load(
+            "hanging",
+            dir.path(),
+            &PluginManifest::default(),
+            Path::new("python3"),
+            Some(Duration::from_millis(500)),
+            &standalone_context(),
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let hang = HashMap::from([("hang".to_string(), "yes".to_string())]);
+        assert!(plugin
+            .run(&hang)
+            .is_err_and(|err| err.contains("didn't respond")));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let status = plugin.status(&HashMap::new()).unwrap();
+        assert!(matches!(status["up"], PluginStatusValue::Bool(true)));
+    }
+}
@@ -0,0 +1,120 @@
+"""Host side of an out-of-process Python plugin, runs after the plugin loader.
+
+Started as `python -c <loader + shim> <plugin dir> <package> <entrypoints json> <paws module>`.
+Reads one JSON request per line from stdin and writes one JSON response per line to stdout:
+
+    -> {"action": "run", "config": {"key": "value"}, "context": {"run_id": "...", ...}}
+    <- {"ok": true}
+    <- {"ok": true, "status": {"key": 1}}
+    <- {"ok": false, "error": "..."}
+
+Calls to the `paws` module during a request are written before the response:
+
+    <- {"log": "message"}
+    <- {"metric": {"name": "errors", "value": 1, "tags": {}}}
+    <- {"event": {"name": "restarted", "fields": {}, "tags": {}}}
+
+The first line written is a handshake with the actions the plugin defines.
+Anything the plugin prints goes to stderr so it can't break the protocol.
+"""
+
+import datetime
+import json
+import sys
+import threading
+import traceback
+
+# Actions plugins don't have to define
+OPTIONAL = ("check", "setup", "teardown")
+
+_channel_lock = threading.Lock()
+
+
+def encode(value):
+    """Timestamps as RFC 3339 and durations as ISO 8601, paws types them by the format"""
+    if isinstance(value, datetime.datetime):
+        return value.astimezone().isoformat()
+    if isinstance(value, datetime.timedelta):
+        if value < datetime.timedelta(0):
+            raise ValueError("Negative durations can't be reported: {}".format(value))
+        # Fixed to milliseconds, which paws reads, float seconds would come out as `1e-06`
+        return "PT%d.%03dS" % (value.days * 86400 + value.seconds, value.microseconds // 1000)
+    return str(value)
+
+
+def respond(channel, **response):
+    with _channel_lock:
+        channel.write(json.dumps(response, default=encode) + "\n")
+        channel.flush()
+
+
+class ShimContext:
+    """`paws.context` forwarding reports to paws"""
+
+    def __init__(self, channel, context):
+        self.channel = channel
+        self.run_id = context.get("run_id")
+        self.instance_id = context.get("instance_id")
+        self.iteration = context.get("iteration")
+        self.deadline = context.get("deadline")
+        self.state_dir = context.get("state_dir")
+
+    def log(self, message):
+        respond(self.channel, log=message)
+
+    def emit_metric(self, name, value, tags):
+        respond(self.channel, metric={"name": name, "value": value, "tags": tags})
+
+    def emit_event(self, name, fields, tags):
+        respond(self.channel, event={"name": name, "fields": fields, "tags": tags})
+
+
+def call(channel, functions, request):
+    action = request.get("action")
+    function = functions.get(action)
+    if function is None and action in OPTIONAL:
+        return {"ok": True}
+    if function is None:
+        return {"ok": False, "error": "Plugin has no {} function".format(action)}
+
+    context = ShimContext(channel, request.get("context", {}))
+    result = invoke(function, request.get("config", {}), context)
+    if action != "status":
+        return {"ok": True}
+    if not isinstance(result, dict):
+        return {"ok": False, "error": "status function must return a dict"}
+    # Encoded here so a value that can't be reported fails the call, not the shim
+    status = {}
+    for key, value in result.items():
+        if isinstance(value, (datetime.datetime, datetime.timedelta)):
+            value = encode(value)
+        status[str(key)] = value
+    return {"ok": True, "status": status}
+
+
+def main():
+    channel = sys.stdout
+    sys.stdout = sys.stderr
+
+    try:
+        path, package, entrypoints, paws = sys.argv[1:5]
+        install_paws(paws)
+        import_plugin(path, package, own_process=True)
+        functions = resolve(package, json.loads(entrypoints))
+    except BaseException:
+        respond(channel, ok=False, error=traceback.format_exc())
+        return
+
+    respond(channel, ok=True, functions=list(functions))
+
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        try:
+            response = call(channel, functions, json.loads(line))
+        except BaseException:
+            response = {"ok": False, "error": traceback.format_exc()}
+        respond(channel, **response)
+
+
+main()
@@ -0,0 +1,320 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use paws_config::RecoveryOptions;
+use paws_monitoring::StatusValue;
+
+use super::steady_state::SteadyState;
+use super::PluginContext;
+use crate::intervals::wait_duration;
+use crate::shutdown;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How a single injected fault played out
+#[derive(Debug, Clone)]
+struct Incident {
+    /// Plugin which injected the fault
+    fault: String,
+    injected: DateTime<Utc>,
+    /// First time the health probe failed
+    detected: Option<DateTime<Utc>>,
+    /// First time the probe passed again after that
+    recovered: Option<DateTime<Utc>>,
+}
+
+impl Incident {
+    fn time_to_detect(&self) -> Option<Duration> {
+        self.detected
+            .map(|detected| (detected - self.injected).to_std().unwrap_or_default())
+    }
+
+    /// Counted from the detection, the outage is invisible before that
+    fn time_to_recover(&self) -> Option<Duration> {
+        match (self.detected, self.recovered) {
+            (Some(detected), Some(recovered)) => {
+                Some((recovered - detected).to_std().unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct FaultSummary {
+    incidents: usize,
+    detected: usize,
+    recovered: usize,
+    mean_time_to_detect: Option<Duration>,
+    mean_time_to_recover: Option<Duration>,
+}
+
+fn mean(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+    Some(durations.iter().sum::<Duration>() / durations.len() as u32)
+}
+
+/// Summary per fault type, i.e. per plugin
+fn summarize(incidents: &[Incident]) -> BTreeMap<String, FaultSummary> {
+    let mut by_fault: BTreeMap<String, Vec<&Incident>> = BTreeMap::new();
+    for incident in incidents {
+        by_fault
+            .entry(incident.fault.clone())
+            .or_default()
+            .push(incident);
+    }
+
+    by_fault
+        .into_iter()
+        .map(|(fault, incidents)| {
+            let detect: Vec<Duration> = incidents
+                .iter()
+                .filter_map(|incident| incident.time_to_detect())
+                .collect();
+            let recover: Vec<Duration> = incidents
+                .iter()
+                .filter_map(|incident| incident.time_to_recover())
+                .collect();
+            let summary = FaultSummary {
+                incidents: incidents.len(),
+                detected: detect.len(),
+                recovered: recover.len(),
+                mean_time_to_detect: mean(&detect),
+                mean_time_to_recover: mean(&recover),
+            };
+            (fault, summary)
+        })
+        .collect()
+}
+
+fn seconds(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{:.1}s", duration.as_secs_f64()),
+        None => "-".to_string(),
+    }
+}
+
+/// Follows every injected fault with a health probe to time its detection and recovery
+pub struct Recovery {
+    steady_state: Arc<SteadyState>,
+    incidents: Mutex<Vec<Incident>>,
+    trackers: Mutex<Vec<JoinHandle<()>>>,
+    /// Instances with an incident still followed, one at a time per instance
+    open: Mutex<HashSet<String>>,
+}
+
+impl Recovery {
+    pub fn new(steady_state: Arc<SteadyState>) -> Self {
+        Recovery {
+            steady_state,
+            incidents: Mutex::default(),
+            trackers: Mutex::default(),
+            open: Mutex::default(),
+        }
+    }
+
+    /// Starts following a fault injected right now and held for `hold`.
+    /// Skipped while the previous fault of the instance is still followed.
+    pub fn track(
+        self: &Arc<Self>,
+        fault: &str,
+        options: &RecoveryOptions,
+        hold: Option<Duration>,
+        context: Arc<PluginContext>,
+    ) {
+        if !self
+            .open
+            .lock()
+            .unwrap()
+            .insert(context.instance_id.clone())
+        {
+            context.log("Previous incident is still open, this one is not timed");
+            return;
+        }
+
+        let recovery = self.clone();
+        let options = options.clone();
+        let mut incident = Incident {
+            fault: fault.to_string(),
+            injected: Utc::now(),
+            detected: None,
+            recovered: None,
+        };
+
+        let tracker = thread::spawn(move || {
+            recovery.follow(&mut incident, &options, hold.unwrap_or_default());
+            report(&incident, &context);
+            recovery.incidents.lock().unwrap().push(incident);
+            recovery.open.lock().unwrap().remove(&context.instance_id);
+        });
+        self.trackers.lock().unwrap().push(tracker);
+    }
+
+    /// Polls the probe until the system recovers, the timeout runs out or the run is interrupted.
+    /// The timeout counts from the revert, a held fault isn't expected to recover before that.
+    fn follow(&self, incident: &mut Incident, options: &RecoveryOptions, hold: Duration) {
+        let interval = options
+            .interval
+            .as_ref()
+            .map(|interval| interval.as_std())
+            .unwrap_or(DEFAULT_INTERVAL);
+        let timeout = options
+            .timeout
+            .as_ref()
+            .map(|timeout| timeout.as_std())
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let until = Instant::now() + hold + timeout;
+
+        while !shutdown::is_requested() && Instant::now() < until {
+            let healthy = self.steady_state.is_healthy(&options.probe).unwrap_or(true);
+            let now = Utc::now();
+            match (healthy, incident.detected) {
+                (false, None) => incident.detected = Some(now),
+                (true, Some(_)) => {
+                    incident.recovered = Some(now);
+                    return;
+                }
+                _ => {}
+            }
+            wait_duration(interval);
+        }
+    }
+
+    /// Waits for the faults which are still followed and hands over all of them
+    pub fn finish(&self) -> Incidents {
+        let trackers: Vec<JoinHandle<()>> = self.trackers.lock().unwrap().drain(..).collect();
+        let pending = trackers
+            .iter()
+            .filter(|tracker| !tracker.is_finished())
+            .count();
+        if pending > 0 {
+            println!("Waiting for {} incident(s) to recover...", pending);
+        }
+        for tracker in trackers {
+            let _ = tracker.join();
+        }
+        Incidents(std::mem::take(&mut self.incidents.lock().unwrap()))
+    }
+}
+
+/// Every fault followed during the run
+pub struct Incidents(Vec<Incident>);
+
+impl Incidents {
+    pub fn print_summary(&self) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let summary = summarize(&self.0);
+        let width = summary.keys().map(String::len).max().unwrap_or_default();
+        println!("Recovery:");
+        for (fault, summary) in summary {
+            println!(
+                "  {:width$}  incidents: {}  detected: {}  recovered: {}  time to detect: {}  time to recover: {}",
+                fault,
+                summary.incidents,
+                summary.detected,
+                summary.recovered,
+                seconds(summary.mean_time_to_detect),
+                seconds(summary.mean_time_to_recover),
+            );
+        }
+    }
+}
+
+/// Sends the incident as an `incident` event and its timings as metrics
+fn report(incident: &Incident, context: &PluginContext) {
+    let mut fields = HashMap::from([(
+        "injected".to_string(),
+        StatusValue::Timestamp(incident.injected.into()),
+    )]);
+    if let Some(detected) = incident.detected {
+        fields.insert(
+            "detected".to_string(),
+            StatusValue::Timestamp(detected.into()),
+        );
+    }
+    if let Some(recovered) = incident.recovered {
+        fields.insert(
+            "recovered".to_string(),
+            StatusValue::Timestamp(recovered.into()),
+        );
+    }
+
+    let time_to_detect = incident.time_to_detect();
+    let time_to_recover = incident.time_to_recover();
+    for (name, value) in [
+        ("time_to_detect", time_to_detect),
+        ("time_to_recover", time_to_recover),
+    ] {
+        if let Some(value) = value {
+            fields.insert(name.to_string(), StatusValue::Duration(value));
+            context.emit_metric(name, StatusValue::Duration(value), HashMap::new());
+        }
+    }
+    context.emit_event("incident", fields, HashMap::new());
+
+    context.log(&match (time_to_detect, time_to_recover) {
+        (None, _) => "Fault was not detected by the health probe".to_string(),
+        (Some(detect), None) => format!("Fault detected in {}, no recovery", seconds(Some(detect))),
+        (Some(detect), Some(recover)) => format!(
+            "Fault detected in {}, recovered in {}",
+            seconds(Some(detect)),
+            seconds(Some(recover))
+        ),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{summarize, FaultSummary, Incident};
+
+    fn incident(fault: &str, detected: Option<i64>, recovered: Option<i64>) -> Incident {
+        let at = |seconds: i64| Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap();
+        Incident {
+            fault: fault.to_string(),
+            injected: at(0),
+            detected: detected.map(at),
+            recovered: recovered.map(at),
+        }
+    }
+
+    #[test]
+    fn test_summary_per_fault() {
+        let summary = summarize(&[
+            incident("dropper", Some(2), Some(12)),
+            incident("dropper", Some(4), Some(24)),
+            incident("dropper", Some(6), None),
+            incident("deathloop", None, None),
+        ]);
+
+        assert_eq!(
+            summary["dropper"],
+            FaultSummary {
+                incidents: 3,
+                detected: 3,
+                recovered: 2,
+                mean_time_to_detect: Some(Duration::from_secs(4)),
+                mean_time_to_recover: Some(Duration::from_secs(15)),
+            }
+        );
+        assert_eq!(
+            summary["deathloop"],
+            FaultSummary {
+                incidents: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use paws_config::{
+    Condition, PluginConfig, ProbeConfig, ProbeSource, SteadyStateOptions, Threshold,
+};
+use paws_install::PluginSearchPath;
+use paws_monitoring::StatusValue;
+
+use super::builtin_probe::BuiltinProbe;
+use super::{load_plugin, CallablePlugin, PluginContext, PluginStatusValue, PreflightCheck};
+use crate::shutdown;
+use crate::stdout_styling::style_line;
+
+/// How often the probes are checked during the run unless configured
+const DEFAULT_FREQUENCY: Duration = Duration::from_secs(10);
+
+/// Checked when a built-in probe has no tolerance configured
+const BUILTIN_TOLERANCE: &str = "ok == true";
+
+struct Probe {
+    name: String,
+    plugin: CallablePlugin,
+    options: HashMap<String, String>,
+    tolerance: Vec<Condition>,
+}
+
+impl Probe {
+    fn load(
+        config: &ProbeConfig,
+        search_path: &PluginSearchPath,
+        context: &Arc<PluginContext>,
+    ) -> Result<Self, String> {
+        let mut tolerance = config.tolerance.clone();
+        let (plugin, options): (CallablePlugin, _) = match &config.source {
+            ProbeSource::Plugin { plugin, options } => {
+                let plugconf = PluginConfig {
+                    name: plugin.clone(),
+                    options: options.clone(),
+                    ..Default::default()
+                };
+                let options = options.clone().unwrap_or_default();
+                // Every probe plugin keeps its own state and is tagged as its own instance
+                let context = Arc::new(PluginContext::new(
+                    context.run_id,
+                    plugin,
+                    &format!("{}-{}", context.instance_id, config.name),
+                    context.deadline,
+                    context.tags(),
+                    context.reporter().clone(),
+                ));
+                let plugin = load_plugin(&plugconf, search_path, &context)?;
+                plugin.check(&options)?;
+                plugin.setup(&options)?;
+                (plugin, options)
+            }
+            source => {
+                if tolerance.is_empty() {
+                    tolerance.push(BUILTIN_TOLERANCE.parse()?);
+                }
+                (Box::new(BuiltinProbe::new(source)?), HashMap::new())
+            }
+        };
+
+        Ok(Probe {
+            name: config.name.clone(),
+            plugin,
+            options,
+            tolerance,
+        })
+    }
+
+    /// Conditions the current status breaks, a failed probe breaks all of them.
+    /// The status is reported as metrics tagged with the probe name.
+    fn violations(&self, context: &PluginContext) -> Vec<String> {
+        let status = match self.plugin.status(&self.options) {
+            Ok(status) => status,
+            Err(err) => return vec![format!("probe failed: {}", err)],
+        };
+
+        let mut tags = context.tags();
+        tags.insert("probe".to_string(), self.name.clone());
+        context.reporter().metric(
+            tags,
+            status
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.into()))
+                .collect(),
+        );
+
+        let error = match status.get("error") {
+            Some(PluginStatusValue::String(error)) => format!(" ({})", error),
+            _ => String::new(),
+        };
+        self.tolerance
+            .iter()
+            .filter_map(|condition| violation(condition, &status))
+            .map(|violation| violation + &error)
+            .collect()
+    }
+
+    fn teardown(&self) {
+        if let Err(err) = self.plugin.teardown(&self.options) {
+            println!("! WARNING: Teardown of probe {} failed: {}", self.name, err);
+        }
+    }
+}
+
+fn describe(value: &PluginStatusValue) -> String {
+    match value {
+        PluginStatusValue::Bool(value) => value.to_string(),
+        PluginStatusValue::Int(value) => value.to_string(),
+        PluginStatusValue::UInt(value) => value.to_string(),
+        PluginStatusValue::Float(value) => value.to_string(),
+        PluginStatusValue::String(value) => format!("\"{}\"", value),
+        PluginStatusValue::Duration(value) => format!("{}s", value.as_secs_f64()),
+        PluginStatusValue::Timestamp(value) => value.to_rfc3339(),
+    }
+}
+
+/// Durations are compared in seconds
+fn as_number(value: &PluginStatusValue) -> Option<f64> {
+    match value {
+        PluginStatusValue::Int(value) => Some(*value as f64),
+        PluginStatusValue::UInt(value) => Some(*value as f64),
+        PluginStatusValue::Float(value) => Some(*value),
+        PluginStatusValue::Duration(value) => Some(value.as_secs_f64()),
+        _ => None,
+    }
+}
+
+/// Why the status doesn't meet the condition, `None` if it does
+pub(super) fn violation(
+    condition: &Condition,
+    status: &HashMap<String, PluginStatusValue>,
+) -> Option<String> {
+    let Some(value) = status.get(&condition.field) else {
+        return Some(format!("{}: {} is missing", condition, condition.field));
+    };
+    let holds = match (&condition.threshold, value) {
+        (Threshold::Bool(threshold), PluginStatusValue::Bool(value)) => {
+            condition.comparison.holds(value, threshold)
+        }
+        (Threshold::Text(threshold), PluginStatusValue::String(value)) => {
+            condition.comparison.holds(value, threshold)
+        }
+        (Threshold::Number(threshold), value) => match as_number(value) {
+            Some(value) => condition.comparison.holds(value, *threshold),
+            None => false,
+        },
+        _ => false,
+    };
+    if holds {
+        return None;
+    }
+
+    Some(format!("{}: got {}", condition, describe(value)))
+}
+
+/// Probes telling whether the system is healthy, checked before, during and after the run
+pub struct SteadyState {
+    probes: Vec<Probe>,
+    frequency: Duration,
+    context: Arc<PluginContext>,
+}
+
+impl SteadyState {
+    /// Probes which failed to load are returned with their errors for the preflight check
+    pub fn load(
+        options: &SteadyStateOptions,
+        search_path: &PluginSearchPath,
+        context: &Arc<PluginContext>,
+    ) -> (Self, Vec<PreflightCheck>) {
+        let mut probes = Vec::new();
+        let mut checks = Vec::new();
+        for config in &options.probes {
+            let check_name = format!("probe {}", config.name);
+            match Probe::load(config, search_path, context) {
+                Ok(probe) => {
+                    probes.push(probe);
+                    checks.push((check_name, Ok(())));
+                }
+                Err(err) => checks.push((check_name, Err(err))),
+            }
+        }
+
+        let steady_state = SteadyState {
+            probes,
+            frequency: options
+                .frequency
+                .as_ref()
+                .map(|frequency| frequency.as_std())
+                .unwrap_or(DEFAULT_FREQUENCY),
+            context: context.clone(),
+        };
+        (steady_state, checks)
+    }
+
+    fn log(&self, message: String) {
+        println!("{}", style_line("steady state".to_string(), message));
+    }
+
+    /// Checks every probe and reports the outcome of the `phase` as a `steady_state` event
+    pub fn verify(&self, phase: &str) -> bool {
+        let mut violations = 0;
+        for probe in &self.probes {
+            for violation in probe.violations(&self.context) {
+                self.log(format!("{} VIOLATED {}", probe.name, violation));
+                violations += 1;
+            }
+        }
+
+        let met = violations == 0;
+        self.log(format!(
+            "{}: {}",
+            phase,
+            if met { "met" } else { "NOT met" }
+        ));
+        self.context.emit_event(
+            "steady_state",
+            HashMap::from([
+                ("phase".to_string(), StatusValue::String(phase.to_string())),
+                ("met".to_string(), StatusValue::Bool(met)),
+                ("violations".to_string(), StatusValue::Int(violations)),
+            ]),
+            HashMap::new(),
+        );
+        met
+    }
+
+    /// Checks the probes until `stop` is dropped or the run is interrupted.
+    /// A violation stops the run, the thread then returns `true`.
+    pub fn monitor(self: &Arc<Self>, stop: Receiver<()>) -> JoinHandle<bool> {
+        let steady_state = self.clone();
+        thread::spawn(move || loop {
+            match stop.recv_timeout(steady_state.frequency) {
+                Err(RecvTimeoutError::Timeout) if !shutdown::is_requested() => {}
+                _ => return false,
+            }
+            if !steady_state.verify("during") {
+                steady_state.log("Violated, stopping the run".to_string());
+                shutdown::request();
+                return true;
+            }
+        })
+    }
+
+    /// Whether a single probe meets its tolerance right now, `None` for unknown probes
+    pub fn is_healthy(&self, name: &str) -> Option<bool> {
+        self.probes
+            .iter()
+            .find(|probe| probe.name == name)
+            .map(|probe| probe.violations(&self.context).is_empty())
+    }
+
+    pub fn teardown(&self) {
+        for probe in &self.probes {
+            probe.teardown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::violation;
+    use crate::plug::PluginStatusValue;
+
+    fn status() -> HashMap<String, PluginStatusValue> {
+        HashMap::from([
+            ("error_rate".to_string(), PluginStatusValue::Float(0.2)),
+            ("healthy".to_string(), PluginStatusValue::Bool(true)),
+            (
+                "state".to_string(),
+                PluginStatusValue::String("degraded".to_string()),
+            ),
+            (
+                "latency".to_string(),
+                PluginStatusValue::Duration(Duration::from_millis(150)),
+            ),
+        ])
+    }
+
+    fn check(condition: &str) -> Option<String> {
+        violation(&condition.parse().unwrap(), &status())
+    }
+
+    #[test]
+    fn test_tolerance() {
+        assert_eq!(check("healthy == true"), None);
+        assert_eq!(check("latency < 0.5"), None);
+        assert_eq!(check("state != ok"), None);
+        assert_eq!(
+            check("error_rate < 0.05"),
+            Some("error_rate < 0.05: got 0.2".to_string())
+        );
+        assert_eq!(
+            check("state == ok"),
+            Some("state == \"ok\": got \"degraded\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mismatched_types_violate() {
+        assert!(check("healthy > 1").is_some());
+        assert!(check("error_rate == true").is_some());
+        assert_eq!(
+            check("queue_depth < 10"),
+            Some("queue_depth < 10: queue_depth is missing".to_string())
+        );
+    }
+}
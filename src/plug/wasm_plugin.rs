@@ -0,0 +1,392 @@
+use crate::plug::command_plugin::parse_key_value_status;
+use crate::plug::{CallablePlugin, PluginInterface};
+use paws_config::{PluginManifest, SandboxOptions};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use wasmtime::{Caller, Engine, Extern, Linker, Module, Store};
+use wasmtime_wasi::pipe::MemoryOutputPipe;
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+use super::{PluginStatusValue, WASM_MODULE_FILE_NAME};
+
+const OUTPUT_CAPACITY: usize = 1024 * 1024;
+
+struct HostState {
+    wasi: WasiP1Ctx,
+    commands: Vec<String>,
+    /// Host env vars passed through by the sandbox config, the only env a spawned program gets
+    env: Vec<(String, String)>,
+}
+
+/// WASI command module called like a CLI: `plugin.wasm <run|status|rollback>`.
+/// Options come as env vars, status is printed as `key=value` lines,
+/// non-zero exit code is a failure.
+struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    linker: Linker<HostState>,
+    sandbox: SandboxOptions,
+}
+
+/// Whether a granted command line allows `argv`.
+/// The grant is the absolute path of the program and its arguments, `*` stands for any single argument.
+fn allows(grant: &str, argv: &[&str]) -> bool {
+    let grant: Vec<&str> = grant.split_whitespace().collect();
+    grant.len() == argv.len()
+        && grant
+            .iter()
+            .zip(argv)
+            .enumerate()
+            .all(|(index, (granted, arg))| granted == arg || (index > 0 && *granted == "*"))
+}
+
+/// `paws.spawn(argv_ptr, argv_len) -> exit code` host function.
+/// `argv` is a nul-separated list of the program and its arguments,
+/// only command lines granted in the sandbox config can be spawned.
+/// The program gets no stdin and only the env passed to the sandbox.
+fn spawn(mut caller: Caller<'_, HostState>, ptr: i32, len: i32) -> i32 {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return -1;
+    };
+    let mut argv = vec![0u8; len.max(0) as usize];
+    if memory.read(&caller, ptr as usize, &mut argv).is_err() {
+        return -1;
+    }
+
+    let argv = String::from_utf8_lossy(&argv);
+    let argv: Vec<&str> = argv.split('\0').filter(|arg| !arg.is_empty()).collect();
+    let Some((program, args)) = argv.split_first() else {
+        return -1;
+    };
+    let state = caller.data();
+    if !state.commands.iter().any(|grant| allows(grant, &argv)) {
+        println!(
+            "! WARNING: plugin tried to spawn `{}` which is not allowed",
+            argv.join(" ")
+        );
+        return -1;
+    }
+
+    let status = Command::new(program)
+        .args(args)
+        .env_clear()
+        .envs(state.env.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status();
+    match status {
+        Ok(status) => status.code().unwrap_or(-1),
+        Err(err) => {
+            println!("! WARNING: plugin can't spawn {}: {}", program, err);
+            -1
+        }
+    }
+}
+
+impl WasmPlugin {
+    fn passed_env(&self) -> Vec<(String, String)> {
+        self.sandbox
+            .env
+            .iter()
+            .flatten()
+            .filter_map(|key| Some((key.clone(), std::env::var(key).ok()?)))
+            .collect()
+    }
+
+    fn build_context(
+        &self,
+        action: &str,
+        config: &HashMap<String, String>,
+        stdout: MemoryOutputPipe,
+    ) -> Result<WasiP1Ctx, String> {
+        let mut builder = WasiCtxBuilder::new();
+        builder
+            .args(&[self.name.as_str(), action])
+            .stdout(stdout)
+            .inherit_stderr();
+
+        for (key, value) in self.passed_env() {
+            builder.env(key, value);
+        }
+        for (key, value) in config {
+            builder.env(key, value);
+        }
+        for dir in self.sandbox.dirs.iter().flatten() {
+            // Writes have to be granted explicitly
+            let (dir_perms, file_perms) = match dir.read_only {
+                Some(false) => (DirPerms::all(), FilePerms::all()),
+                _ => (DirPerms::READ, FilePerms::READ),
+            };
+            builder
+                .preopened_dir(&dir.host, &dir.guest, dir_perms, file_perms)
+                .map_err(|err| format!("Can't mount {}: {}", dir.host.display(), err))?;
+        }
+
+        Ok(builder.build_p1())
+    }
+
+    fn call(&self, action: &str, config: &HashMap<String, String>) -> Result<String, String> {
+        let stdout = MemoryOutputPipe::new(OUTPUT_CAPACITY);
+        let state = HostState {
+            wasi: self.build_context(action, config, stdout.clone())?,
+            commands: self.sandbox.commands.clone().unwrap_or_default(),
+            env: self.passed_env(),
+        };
+        let mut store = Store::new(&self.engine, state);
+
+        let instance = self
+            .linker
+            .instantiate(&mut store, &self.module)
+            .map_err(|err| format!("Can't instantiate plugin {}: {}", self.name, err))?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(|err| format!("Plugin {} is not a WASI command: {}", self.name, err))?;
+
+        let code = match start.call(&mut store, ()) {
+            Ok(()) => 0,
+            Err(err) => match err.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None => return Err(format!("Plugin {} trapped: {}", self.name, err)),
+            },
+        };
+
+        let output = String::from_utf8_lossy(&stdout.contents()).to_string();
+        match code {
+            0 => Ok(output),
+            code => Err(format!("{} {} exited with {}", self.name, action, code)),
+        }
+    }
+}
+
+impl PluginInterface for WasmPlugin {
+    fn run(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        let output = self.call("run", config)?;
+        if !output.is_empty() {
+            println!("Run stdout: {}", output.trim_end());
+        }
+        Ok(())
+    }
+
+    fn status(
+        &self,
+        config: &HashMap<String, String>,
+    ) -> Result<HashMap<String, PluginStatusValue>, String> {
+        let output = self.call("status", config)?;
+        Ok(parse_key_value_status(output.as_bytes()))
+    }
+
+    fn rollback(&self, config: &HashMap<String, String>) -> Result<(), String> {
+        let output = self.call("rollback", config)?;
+        if !output.is_empty() {
+            println!("Rollback stdout: {}", output.trim_end());
+        }
+        Ok(())
+    }
+}
+
+pub fn load(
+    name: &str,
+    path_to_plugin: &Path,
+    manifest: &PluginManifest,
+    sandbox: Option<&SandboxOptions>,
+) -> Result<CallablePlugin, String> {
+    let path_to_module = path_to_plugin.join(
+        manifest
+            .run
+            .entrypoint
+            .clone()
+            .unwrap_or(PathBuf::from(WASM_MODULE_FILE_NAME)),
+    );
+    if !path_to_module.exists() {
+        return Err(format!(
+            "No {} found for plugin: {}",
+            path_to_module.display(),
+            name
+        ));
+    }
+
+    // A bare name would be looked up in PATH, which the plugin doesn't control but can't see either
+    for grant in sandbox
+        .iter()
+        .flat_map(|sandbox| sandbox.commands.iter().flatten())
+    {
+        if !grant
+            .split_whitespace()
+            .next()
+            .is_some_and(|program| Path::new(program).is_absolute())
+        {
+            return Err(format!(
+                "Granted command `{}` of plugin {} has to start with an absolute path",
+                grant, name
+            ));
+        }
+    }
+
+    let engine = Engine::default();
+    let module = Module::from_file(&engine, &path_to_module)
+        .map_err(|err| format!("Can't compile {}: {}", path_to_module.display(), err))?;
+
+    let mut linker: Linker<HostState> = Linker::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |state: &mut HostState| &mut state.wasi)
+        .map_err(|err| err.to_string())?;
+    linker
+        .func_wrap("paws", "spawn", spawn)
+        .map_err(|err| err.to_string())?;
+
+    Ok(Box::new(WasmPlugin {
+        name: name.to_string(),
+        engine,
+        module,
+        linker,
+        sandbox: sandbox.cloned().unwrap_or_default(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use paws_config::{PluginManifest, SandboxDir, SandboxOptions};
+
+    use super::{allows, load};
+    use crate::plug::PluginStatusValue;
+
+    // Prints "count=3" and exits with whatever `paws.spawn("/bin/true")` returned
+    const MODULE: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (import "paws" "spawn" (func $spawn (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\08\00\00\00\08\00\00\00")
+  (data (i32.const 8) "count=3\n")
+  (data (i32.const 16) "/bin/true\00")
+  (func (export "_start")
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 32)))
+    (call $proc_exit (call $spawn (i32.const 16) (i32.const 10)))))
+"#;
+
+    // Creates "created" in the first mounted dir and exits with the errno of the call
+    const WRITING_MODULE: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "created")
+  (func (export "_start")
+    (call $proc_exit (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 7)
+      (i32.const 1) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 16)))))
+"#;
+
+    fn load_module(sandbox: SandboxOptions) -> Result<crate::plug::CallablePlugin, String> {
+        load_source(MODULE, sandbox)
+    }
+
+    fn load_source(
+        source: &str,
+        sandbox: SandboxOptions,
+    ) -> Result<crate::plug::CallablePlugin, String> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(crate::plug::WASM_MODULE_FILE_NAME), source).unwrap();
+        load(
+            "wasm",
+            dir.path(),
+            &PluginManifest::default(),
+            Some(&sandbox),
+        )
+    }
+
+    #[test]
+    fn test_spawn_requires_grant() {
+        let plugin = load_module(SandboxOptions::default()).unwrap();
+
+        assert!(plugin.run(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_status_with_granted_command() {
+        let plugin = load_module(SandboxOptions {
+            commands: Some(vec!["/bin/true".to_string()]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let status = plugin.status(&HashMap::new()).unwrap();
+        assert!(matches!(status["count"], PluginStatusValue::Int(3)));
+    }
+
+    #[test]
+    fn test_dirs_are_read_only_unless_granted() {
+        let mounted = tempfile::tempdir().unwrap();
+        let sandbox = |read_only: Option<bool>| SandboxOptions {
+            dirs: Some(vec![SandboxDir {
+                host: mounted.path().to_path_buf(),
+                guest: "/data".to_string(),
+                read_only,
+            }]),
+            ..Default::default()
+        };
+
+        for read_only in [None, Some(true)] {
+            let plugin = load_source(WRITING_MODULE, sandbox(read_only)).unwrap();
+            assert!(plugin.run(&HashMap::new()).is_err());
+            assert!(!mounted.path().join("created").exists());
+        }
+
+        let plugin = load_source(WRITING_MODULE, sandbox(Some(false))).unwrap();
+        assert!(plugin.run(&HashMap::new()).is_ok());
+        assert!(mounted.path().join("created").exists());
+    }
+
+    #[test]
+    fn test_grants_are_absolute() {
+        let sandbox = |grant: &str| SandboxOptions {
+            commands: Some(vec![grant.to_string()]),
+            ..Default::default()
+        };
+
+        assert!(load_module(sandbox("true")).is_err());
+        assert!(load_module(sandbox("bin/true")).is_err());
+    }
+
+    #[test]
+    fn test_granted_arguments() {
+        let grant = "/usr/sbin/iptables -A INPUT -p tcp --dport * -j DROP";
+
+        assert!(allows(
+            grant,
+            &[
+                "/usr/sbin/iptables",
+                "-A",
+                "INPUT",
+                "-p",
+                "tcp",
+                "--dport",
+                "80",
+                "-j",
+                "DROP"
+            ]
+        ));
+        assert!(!allows(
+            grant,
+            &[
+                "/usr/sbin/iptables",
+                "-A",
+                "INPUT",
+                "-p",
+                "tcp",
+                "--dport",
+                "80",
+                "-j",
+                "ACCEPT"
+            ]
+        ));
+        assert!(!allows(grant, &["/usr/sbin/iptables", "-F"]));
+        assert!(!allows("* -c true", &["/bin/sh", "-c", "true"]));
+        assert!(allows("/bin/true", &["/bin/true"]));
+        assert!(!allows("/bin/true", &["/bin/true", "--help"]));
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use paws_config::check_plain_name;
+
+const CONFIG_TEMPLATE: &str = include_str!("scaffold/paws.yml");
+const MANIFEST_TEMPLATE: &str = include_str!("scaffold/plugin.yml");
+const BASH_RUN_TEMPLATE: &str = include_str!("scaffold/bash/run.sh");
+const BASH_STATUS_TEMPLATE: &str = include_str!("scaffold/bash/status.sh");
+const BASH_README_TEMPLATE: &str = include_str!("scaffold/bash/README.md");
+const PYTHON_MAIN_TEMPLATE: &str = include_str!("scaffold/python/main.py");
+const PYTHON_README_TEMPLATE: &str = include_str!("scaffold/python/README.md");
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum PluginTemplate {
+    Bash,
+    Python,
+}
+
+impl PluginTemplate {
+    fn kind(&self) -> &'static str {
+        match self {
+            PluginTemplate::Bash => "bash",
+            PluginTemplate::Python => "python",
+        }
+    }
+
+    fn files(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            PluginTemplate::Bash => vec![
+                ("run.sh", BASH_RUN_TEMPLATE),
+                ("status.sh", BASH_STATUS_TEMPLATE),
+                ("README.md", BASH_README_TEMPLATE),
+            ],
+            PluginTemplate::Python => vec![
+                ("main.py", PYTHON_MAIN_TEMPLATE),
+                ("README.md", PYTHON_README_TEMPLATE),
+            ],
+        }
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+pub fn init_config(path: &Path, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if path.exists() && !force {
+        println!(
+            "Config {} already exists, use --force to overwrite it",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, CONFIG_TEMPLATE)?;
+    println!("Created config {}", path.display());
+
+    Ok(())
+}
+
+pub fn new_plugin(
+    name: &str,
+    template: PluginTemplate,
+    parent: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    // The name ends up in paths and the manifest, it can't point outside of `parent`
+    check_plain_name(name).map_err(|err| format!("Plugin name {}", err))?;
+
+    let plugin_path = parent.join(name);
+    if plugin_path.exists() {
+        return Err(format!("{} already exists", plugin_path.display()).into());
+    }
+    fs::create_dir_all(&plugin_path)?;
+
+    let mut files = template.files();
+    files.push(("plugin.yml", MANIFEST_TEMPLATE));
+    for (file_name, contents) in files {
+        let file_path = plugin_path.join(file_name);
+        let contents = contents
+            .replace("{{name}}", name)
+            .replace("{{kind}}", template.kind());
+        fs::write(&file_path, contents)?;
+        if file_name.ends_with(".sh") {
+            make_executable(&file_path)?;
+        }
+    }
+
+    println!(
+        "Created {} plugin {}",
+        template.kind(),
+        plugin_path.display()
+    );
+    Ok(plugin_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use paws_config::{load_manifest, KittypawsConfig, PluginKind};
+
+    use super::{new_plugin, PluginTemplate, CONFIG_TEMPLATE};
+
+    #[test]
+    fn test_starter_config_is_valid() {
+        let config = KittypawsConfig::from(CONFIG_TEMPLATE.to_string());
+        assert!(config.plugins.is_empty());
+    }
+
+    #[test]
+    fn test_new_bash_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = new_plugin("breaker", PluginTemplate::Bash, dir.path()).unwrap();
+
+        assert!(path.join("run.sh").exists());
+        assert!(path.join("status.sh").exists());
+        let manifest = load_manifest(&path).unwrap().unwrap();
+        assert_eq!(manifest.name.as_deref(), Some("breaker"));
+        assert_eq!(manifest.kind, Some(PluginKind::Bash));
+    }
+
+    #[test]
+    fn test_new_plugin_does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        new_plugin("breaker", PluginTemplate::Python, dir.path()).unwrap();
+
+        assert!(new_plugin("breaker", PluginTemplate::Python, dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_new_plugin_name_is_not_a_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let parent = dir.path().join("plugins");
+
+        for name in ["../breaker", "nested/breaker", "/tmp/breaker", "..", ""] {
+            assert!(
+                new_plugin(name, PluginTemplate::Bash, &parent).is_err(),
+                "{} is accepted",
+                name
+            );
+        }
+        assert!(!dir.path().join("breaker").exists());
+    }
+}
@@ -0,0 +1,7 @@
+#!/usr/bin/env bash
+# Called on every run. Options from the config come as env vars.
+set -euo pipefail
+
+target=${target:?target option is required}
+
+echo "Breaking ${target}"
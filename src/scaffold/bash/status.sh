@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Called by the status loop. Print one key=value per line.
+set -euo pipefail
+
+target=${target:?target option is required}
+
+echo "target=${target}"
+echo "healthy=1"
@@ -0,0 +1,17 @@
+from typing import Dict, Union
+
+
+def run(config: Dict[str, str]) -> None:
+    """Called on every run with options from the config."""
+    target = config["target"]
+    print(f"Breaking {target}")
+
+
+def status(config: Dict[str, str]) -> Dict[str, Union[int, float, str]]:
+    """Called by the status loop, values are sent to monitoring."""
+    return {"target": config["target"], "healthy": 1}
+
+
+def rollback(config: Dict[str, str]) -> None:
+    """Reverts whatever run did, used by `paws exec --rollback`."""
+    pass
@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Set once the run is interrupted, plugin loops stop at the next chance and plugins are torn down
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+/// Interrupts received so far, kept apart from `SHUTDOWN` which paws can set itself
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn is_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Stops the run the same way an interrupt does
+pub fn request() {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    // Only async-signal-safe calls in here
+    if SIGNALS.fetch_add(1, Ordering::SeqCst) > 0 {
+        unsafe { libc::_exit(130) };
+    }
+    SHUTDOWN.store(true, Ordering::SeqCst);
+    let message = b"\nShutting down, interrupt again to exit without teardown\n";
+    unsafe {
+        libc::write(
+            libc::STDERR_FILENO,
+            message.as_ptr() as *const libc::c_void,
+            message.len(),
+        )
+    };
+}
+
+/// First Ctrl-C or SIGTERM requests a shutdown, the second one exits right away
+pub fn install_handler() {
+    #[cfg(unix)]
+    unsafe {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
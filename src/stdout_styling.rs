@@ -1,17 +1,6 @@
 use chrono::{DateTime, Utc};
-use std::ops::{Range, Sub};
 use std::time::SystemTime;
 
-trait Diff<T> {
-    fn get_diff(&self) -> T;
-}
-
-impl<T: Sub<Output = T> + Copy> Diff<T> for Range<T> {
-    fn get_diff(&self) -> T {
-        self.end - self.start
-    }
-}
-
 fn get_plugname_format(name: &str) -> String {
     format!("[{}]", name)
 }